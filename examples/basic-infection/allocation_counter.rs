@@ -0,0 +1,49 @@
+/*!
+
+A global allocator that counts heap allocations, so that the example can report how many it
+makes. Compare a run with and without the `smallvec` feature to see what storing subscriber
+lists inline saves.
+
+*/
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering}
+};
+
+pub struct CountingAllocator {
+    allocations: AtomicUsize,
+}
+
+impl CountingAllocator {
+    pub const fn new() -> Self {
+        CountingAllocator {
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of allocations, including reallocations, made so far.
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: The caller upholds `GlobalAlloc::alloc()`'s contract, which we pass on.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `System` with `layout`, in `alloc()` or `realloc()`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: `ptr` was allocated by `System` with `layout`, and the caller upholds the
+        // rest of `GlobalAlloc::realloc()`'s contract.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
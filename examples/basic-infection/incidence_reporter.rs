@@ -5,32 +5,62 @@ An `IncidenceReporter` subscribes to status change messages and records them to
 */
 
 use std::{
+    collections::HashMap,
     fs::File,
-    path::PathBuf,
-    io::Write
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
-use csv::Writer;
+use serde_json::Value;
+use csv::{Writer, WriterBuilder};
 
-use actor_model::actor::{Actor, ActorHandle};
+use actor_model::actor::{Actor, ActorError, ActorHandle};
 
 use crate::{
-    message::{Channel, Envelope, Message, RcEnvelope, Topic},
+    message::{ActorResult, Registration, Channel, Envelope, Message, Topic},
     people::{InfectionStatus, PersonID}
 };
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
-struct IncidenceReportItem {
+pub(crate) struct IncidenceReportItem {
     time: f64,
     person_id: PersonID,
+    // Written by name rather than as `InfectionStatus`'s numeric code so the report stays readable.
+    #[serde(with = "status_name")]
     infection_status: InfectionStatus,
 }
 
+// The column names `IncidenceReportItem` is written with. Only needed when the headers are
+// written by hand, i.e. when there is metadata.
+const ITEM_COLUMNS: [&str; 3] = ["time", "person_id", "infection_status"];
+
+mod status_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::people::InfectionStatus;
+
+    pub fn serialize<S: Serializer>(infection_status: &InfectionStatus, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", infection_status))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<InfectionStatus, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "Susceptible" => Ok(InfectionStatus::Susceptible),
+            "Infected"    => Ok(InfectionStatus::Infected),
+            "Recovered"   => Ok(InfectionStatus::Recovered),
+            other         => Err(D::Error::custom(format!("unknown infection status: {}", other))),
+        }
+    }
+}
+
 pub struct IncidenceReporter {
     handle: ActorHandle,
     file_name: PathBuf,
     writer: Option<Writer<File>>,
+    // When non-empty, each row goes to the writer for its `InfectionStatus` instead of `writer`.
+    status_writers: HashMap<InfectionStatus, Writer<File>>,
+    // Constant columns added to every row, as (name, value) pairs in order of name.
+    metadata: Vec<(String, String)>,
 }
 
 impl IncidenceReporter {
@@ -40,6 +70,8 @@ impl IncidenceReporter {
             handle: 0,
             file_name: PathBuf::from(file_name),
             writer: None,
+            status_writers: HashMap::new(),
+            metadata: vec![],
         };
         new_reporter.init_writer().expect("Failed to init file writer");
         // new_reporter.write_headers().expect("Failed to write headers");
@@ -47,14 +79,83 @@ impl IncidenceReporter {
         new_reporter
     }
 
+    // Create a new IncidenceReporter that writes one file per infection status into the
+    // directory `base_path`, e.g. `infected.csv` and `recovered.csv`
+    #[allow(dead_code)]
+    pub fn with_per_status_files(base_path: &Path) -> Self {
+        let mut new_reporter = IncidenceReporter {
+            handle: 0,
+            file_name: base_path.to_path_buf(),
+            writer: None,
+            status_writers: HashMap::new(),
+            metadata: vec![],
+        };
+        new_reporter.init_status_writers().expect("Failed to init file writers");
+
+        new_reporter
+    }
+
+    // Add a constant column to every row for each entry in `metadata`, e.g. the model's
+    // parameters, so each row records where it came from. The columns follow the report's own,
+    // in order of name. Strings are written without quotes, other values as JSON.
+    #[allow(dead_code)]
+    pub fn with_metadata(mut self, metadata: HashMap<String, Value>) -> Self {
+        self.metadata = metadata
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(value) => value,
+                    value                => value.to_string(),
+                };
+                (name, value)
+            })
+            .collect();
+        self.metadata.sort();
+
+        // The headers depend on the metadata, so the files are started over.
+        if self.status_writers.is_empty() {
+            self.init_writer().expect("Failed to init file writer");
+        } else {
+            self.init_status_writers().expect("Failed to init file writers");
+        }
+
+        self
+    }
+
+    // Create the CSV file at `path`. With metadata, the headers can't be derived from
+    // `IncidenceReportItem`, so we write them ourselves.
+    fn create_writer(&self, path: &Path) -> std::io::Result<Writer<File>> {
+        let file = File::create(path)?;
+        if self.metadata.is_empty() {
+            return Ok(Writer::from_writer(file));
+        }
+
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(file);
+        let metadata_columns = self.metadata.iter().map(|(name, _)| name.as_str());
+        writer.write_record(ITEM_COLUMNS.into_iter().chain(metadata_columns))?;
+        Ok(writer)
+    }
+
     // Initialize the writer (creating or opening the CSV file)
     pub fn init_writer(&mut self) -> std::io::Result<()> {
-        let file = File::create(&self.file_name)?;
-        let writer = Writer::from_writer(file);
+        let writer = self.create_writer(&self.file_name)?;
         self.writer = Some(writer);
         Ok(())
     }
 
+    // Initialize one writer per infection status in the directory `self.file_name`
+    pub fn init_status_writers(&mut self) -> std::io::Result<()> {
+        for (infection_status, file_name) in [
+            (InfectionStatus::Susceptible, "susceptible.csv"),
+            (InfectionStatus::Infected   , "infected.csv"),
+            (InfectionStatus::Recovered  , "recovered.csv"),
+        ] {
+            let writer = self.create_writer(&self.file_name.join(file_name))?;
+            self.status_writers.insert(infection_status, writer);
+        }
+        Ok(())
+    }
+
     // Write the headers to the CSV (based on the fields of IncidenceReportItem)
     // pub fn write_headers(&mut self) -> std::io::Result<()> {
     //     if let Some(ref mut writer) = self.writer {
@@ -65,8 +166,19 @@ impl IncidenceReporter {
 
     // Write a row of data from an IncidenceReportItem instance to the CSV
     pub fn write_row(&mut self, item: IncidenceReportItem) -> std::io::Result<()> {
-        if let Some(ref mut writer) = self.writer {
+        let writer = match self.status_writers.get_mut(&item.infection_status) {
+            Some(writer) => Some(writer),
+            None         => self.writer.as_mut(),
+        };
+        let Some(writer) = writer else {
+            return Ok(());
+        };
+
+        if self.metadata.is_empty() {
             writer.serialize(item)?;
+        } else {
+            let metadata_values: Vec<&str> = self.metadata.iter().map(|(_, value)| value.as_str()).collect();
+            writer.serialize((item, metadata_values))?;
         }
         Ok(())
     }
@@ -76,6 +188,9 @@ impl IncidenceReporter {
         if let Some(ref mut writer) = self.writer {
             writer.flush()?;
         }
+        for writer in self.status_writers.values_mut() {
+            writer.flush()?;
+        }
         Ok(())
     }
 }
@@ -87,7 +202,7 @@ impl Drop for IncidenceReporter {
 }
 
 impl Actor<Message, Topic> for IncidenceReporter {
-    fn receive_message(&mut self, envelope: RcEnvelope) -> Vec<RcEnvelope> {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
         // In general, we have a method that responds to every message type we know how to answer.
 
         // The only messages we care about are when a person changes status.
@@ -95,20 +210,23 @@ impl Actor<Message, Topic> for IncidenceReporter {
         let messages =
             match *envelope {
                 Envelope {
-                    channel: Channel::Topic(Topic::PersonStatus),
-                    message: Some(Message::PersonStatus(person_id, infection_status)),
-                    time   : Some(time), // Time is set only when it's a transition.
+                    channel       : Channel::Topic(Topic::PersonStatus),
+                    message       : Some(Message::PersonStatus(person_id, _, infection_status)),
+                    is_timed_event: true, // Only a transition is a timed event.
+                    time,
                     ..
                 }
                 => {
-                    // We write a row representing the status change
-                    self.write_row(
-                        IncidenceReportItem{
-                            time: time.0,
-                            person_id,
-                            infection_status,
-                        }
-                    ).expect("Failed to write row");
+                    // We write a row representing the status change. Without the report there's
+                    // nothing for us to do, so a write failure is fatal.
+                    let row = IncidenceReportItem{
+                        time: time.0,
+                        person_id,
+                        infection_status,
+                    };
+                    if let Err(error) = self.write_row(row) {
+                        return Err(ActorError::Fatal(format!("failed to write row: {}", error)));
+                    }
                     vec![]
                 }
 
@@ -123,10 +241,10 @@ impl Actor<Message, Topic> for IncidenceReporter {
             println!("INCIDENCE REPORTER: {:?}", message);
         }
 
-        messages
+        Ok(messages)
     }
 
-    fn register(&mut self, handle: ActorHandle) -> (Vec<Channel>, Vec<RcEnvelope>) {
+    fn register(&mut self, handle: ActorHandle) -> Registration {
         self.handle = handle;
 
         // We respond to infection status changes. We have no initial messages.
@@ -134,6 +252,52 @@ impl Actor<Message, Topic> for IncidenceReporter {
             Channel::Topic(Topic::PersonStatus),
         ];
 
-        (subscriptions, vec![])
+        Ok((subscriptions, vec![]))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_per_status_files() {
+        let base_path = std::env::temp_dir().join("incidence_reporter_per_status");
+        fs::create_dir_all(&base_path).unwrap();
+
+        let mut reporter = IncidenceReporter::with_per_status_files(&base_path);
+        reporter.write_row(IncidenceReportItem{ time: 1.0, person_id: 3, infection_status: InfectionStatus::Infected }).unwrap();
+        reporter.write_row(IncidenceReportItem{ time: 6.0, person_id: 3, infection_status: InfectionStatus::Recovered }).unwrap();
+        reporter.finish().unwrap();
+
+        let infected  = fs::read_to_string(base_path.join("infected.csv")).unwrap();
+        let recovered = fs::read_to_string(base_path.join("recovered.csv")).unwrap();
+        assert_eq!(infected.lines().count(), 2); // header + row
+        assert!(recovered.contains("Recovered"));
+        assert!(!recovered.contains("Infected"));
+    }
+
+    #[test]
+    fn test_metadata_columns() {
+        let file_name = std::env::temp_dir().join("incidence_reporter_metadata.csv");
+        let metadata  = HashMap::from([
+            ("scenario".to_string(), Value::from("baseline")),
+            ("foi".to_string(), Value::from(0.1)),
+        ]);
+
+        let mut reporter = IncidenceReporter::new(file_name.to_str().unwrap()).with_metadata(metadata);
+        reporter.write_row(IncidenceReportItem{ time: 1.0, person_id: 3, infection_status: InfectionStatus::Infected }).unwrap();
+        reporter.write_row(IncidenceReportItem{ time: 6.0, person_id: 3, infection_status: InfectionStatus::Recovered }).unwrap();
+        reporter.finish().unwrap();
+
+        let report = fs::read_to_string(&file_name).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines, vec![
+            "time,person_id,infection_status,foi,scenario",
+            "1.0,3,Infected,0.1,baseline",
+            "6.0,3,Recovered,0.1,baseline",
+        ]);
     }
 }
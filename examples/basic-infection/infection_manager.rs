@@ -4,11 +4,11 @@ The `InfectionManager` is responsible for
 
 */
 
-use std::rc::Rc;
+use std::{borrow::Cow, collections::HashMap};
 
 use rand::prelude::SmallRng;
 use rand::SeedableRng;
-use rand_distr::{Distribution, Exp};
+use rand_distr::{Distribution, Exp, Exp1};
 
 use actor_model::{
     actor::{Actor, ActorHandle},
@@ -17,44 +17,97 @@ use actor_model::{
 
 use crate::{
     INFECTION_DURATION,
-    message::{RcEnvelope, Envelope, Channel, Message, Topic},
-    people::{InfectionStatus, PersonID}
+    message::{ActorResult, Registration, RcEnvelope, Envelope, Channel, Message, Topic},
+    people::{InfectionStatus, PersonID, SubPopulationID}
 };
 
+/// Identifies a scheduled recovery. The timeline has no handle for the events pushed onto
+/// it, so an event is identified by the time it is scheduled for.
+pub(crate) type EventToken = Time;
+
+/// Maps a person to the mean duration of their infection. With the `send` feature it must be
+/// `Send`, like the actor that owns it.
+#[cfg(not(feature = "send"))]
+pub type PersonDurationMap = Box<dyn Fn(PersonID) -> f64>;
+#[cfg(feature = "send")]
+pub type PersonDurationMap = Box<dyn Fn(PersonID) -> f64 + Send>;
+
 pub struct InfectionManager {
-    handle  : ActorHandle,
-    rng     : SmallRng,
-    exp_dist: Exp<f64>,
+    handle              : ActorHandle,
+    rng                 : SmallRng,
+    exp_dist            : Exp<f64>,
+    /// If set, each person's mean infection duration, overriding `exp_dist`.
+    duration_fn         : Option<PersonDurationMap>,
+    /// The recoveries this manager has scheduled that haven't happened yet.
+    scheduled_recoveries: HashMap<PersonID, EventToken>,
 }
 
 impl InfectionManager {
+    /// An infection manager whose infections last `INFECTION_DURATION` on average.
     pub fn new() -> InfectionManager {
+        InfectionManager::new_with_duration(INFECTION_DURATION)
+    }
+
+    /// An infection manager whose infections last `duration` on average. Useful for running
+    /// several simulations with different durations in the same process.
+    pub fn new_with_duration(duration: f64) -> InfectionManager {
+        assert!(duration > 0.0, "infection duration must be positive");
+
         InfectionManager{
-            handle  : 0,
-            rng     : SmallRng::seed_from_u64(42),
-            exp_dist: Exp::new(1.0 / INFECTION_DURATION).unwrap()
+            handle              : 0,
+            rng                 : SmallRng::seed_from_u64(42),
+            exp_dist            : Exp::new(1.0 / duration).unwrap(),
+            duration_fn         : None,
+            scheduled_recoveries: HashMap::new(),
         }
     }
 
-    fn schedule_recovery(&mut self, person_id: PersonID, time: Time) -> RcEnvelope {
-        let recovery_time = time + self.exp_dist.sample(&mut self.rng);
+    /// Gives each person the mean infection duration `f` returns for them instead of a shared
+    /// one, e.g. for age- or risk-group-stratified recovery. Durations are still exponentially
+    /// distributed.
+    #[allow(dead_code)]
+    pub fn with_duration_fn(mut self, f: PersonDurationMap) -> Self {
+        self.duration_fn = Some(f);
+        self
+    }
 
-        let to_be_scheduled = Message::PersonStatus(person_id, InfectionStatus::Recovered);
+    /// The recoveries scheduled but not yet happened, keyed by the person who will recover.
+    #[allow(dead_code)]
+    pub fn scheduled_recoveries(&self) -> &HashMap<PersonID, EventToken> {
+        &self.scheduled_recoveries
+    }
 
-        let shedule_request = Envelope {
-            from: self.handle,
-            channel: Channel::ScheduleEvent,
-            message: Some(to_be_scheduled),
-            time: Some(recovery_time),
+    /// Schedules the person's recovery and announces it on `Topic::RecoveryScheduled`.
+    fn schedule_recovery(&mut self, person_id: PersonID, sub_population: SubPopulationID, time: Time) -> Vec<RcEnvelope> {
+        let duration = match &self.duration_fn {
+            Some(duration_fn) => {
+                let mean_duration = duration_fn(person_id);
+                assert!(mean_duration > 0.0, "infection duration of person {} must be positive", person_id);
+                // Scaling a unit exponential sample by the mean samples `Exp(1 / mean)`.
+                let unit: f64 = Exp1.sample(&mut self.rng);
+                mean_duration * unit
+            }
+            None => self.exp_dist.sample(&mut self.rng),
         };
-        Rc::new(shedule_request)
+        let recovery_time = time + duration;
+
+        let to_be_scheduled = Message::PersonStatus(person_id, sub_population, InfectionStatus::Recovered);
+
+        let shedule_request = Envelope::new(self.handle, Channel::ScheduleEvent, Some(to_be_scheduled), Some(recovery_time))
+            .with_description("recovery");
+        self.scheduled_recoveries.insert(person_id, recovery_time);
+
+        vec![
+            RcEnvelope::new(shedule_request),
+            Message::make_recovery_scheduled(self.handle, person_id, recovery_time),
+        ]
     }
 
 }
 
 
 impl Actor<Message, Topic> for InfectionManager {
-    fn receive_message(&mut self, envelope: RcEnvelope) -> Vec<RcEnvelope> {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
         // In general, we have a method that responds to every message type we know how to answer.
 
         // The only messages we care about are when a person changes status to infected.
@@ -62,13 +115,30 @@ impl Actor<Message, Topic> for InfectionManager {
         let messages =
         match *envelope {
             Envelope {
-                channel: Channel::Topic(Topic::PersonStatus),
-                message: Some(Message::PersonStatus(person_id, InfectionStatus::Infected)),
-                time   : Some(time), // Time is set only when it's a transition.
+                channel       : Channel::Topic(Topic::PersonStatus),
+                message       : Some(Message::PersonStatus(person_id, sub_population, InfectionStatus::Infected)),
+                is_timed_event: true, // Only a transition is a timed event.
+                time,
+                ..
+            }
+            => {
+                self.schedule_recovery(person_id, sub_population, time)
+            }
+
+            Envelope {
+                channel: Channel::TimelineEvent,
+                message: Some(Message::PersonStatus(person_id, _, InfectionStatus::Recovered)),
                 ..
             }
             => {
-                vec![self.schedule_recovery(person_id, time)]
+                // A recovery we didn't schedule, or one that already happened, points to a bug.
+                match self.scheduled_recoveries.remove(&person_id) {
+                    Some(_) => vec![],
+                    None    => {
+                        let warning = Cow::Owned(format!("double recovery for person {}", person_id));
+                        vec![RcEnvelope::new(Envelope::new(self.handle, Channel::Warning(warning), None, None))]
+                    }
+                }
             }
 
             _ => {
@@ -82,17 +152,19 @@ impl Actor<Message, Topic> for InfectionManager {
             println!("INFECTION MANAGER: {:?}", message);
         }
 
-        messages
+        Ok(messages)
     }
 
-    fn register(&mut self, handle: ActorHandle) -> (Vec<Channel>, Vec<RcEnvelope>) {
+    fn register(&mut self, handle: ActorHandle) -> Registration {
         self.handle = handle;
 
-        // We respond to infection status changes. We have no initial messages.
+        // We respond to infection status changes and watch for the recoveries we scheduled. We
+        // have no initial messages.
         let subscriptions = vec![
             Channel::Topic(Topic::PersonStatus),
+            Channel::TimelineEvent,
         ];
-        (subscriptions, vec![])
+        Ok((subscriptions, vec![]))
     }
 }
 
@@ -138,3 +210,80 @@ mod test {
     }
 }
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_recovery_scheduled() {
+        let mut manager = InfectionManager::new();
+        let response = manager.receive_message(
+            &Message::make_person_status(1, 7, 0, InfectionStatus::Infected, Some(2.0.into()))
+        ).unwrap();
+
+        let recovery_time = manager.scheduled_recoveries()[&7];
+        assert!(recovery_time >= 2.0.into());
+        assert_eq!(response.len(), 2);
+        assert_eq!(response[0].channel, Channel::ScheduleEvent);
+        assert_eq!(response[0].time, recovery_time);
+        assert_eq!(response[1].channel, Channel::Topic(Topic::RecoveryScheduled));
+        assert_eq!(response[1].message, Some(Message::RecoveryScheduled(7, recovery_time)));
+    }
+
+    #[test]
+    fn test_double_recovery_warns() {
+        let mut manager = InfectionManager::new();
+        manager.receive_message(
+            &Message::make_person_status(1, 7, 0, InfectionStatus::Infected, Some(2.0.into()))
+        ).unwrap();
+        let recovery_time = manager.scheduled_recoveries()[&7];
+        let recovery      = Envelope::new(
+            0,
+            Channel::TimelineEvent,
+            Some(Message::PersonStatus(7, 0, InfectionStatus::Recovered)),
+            Some(recovery_time)
+        );
+
+        assert!(manager.receive_message(&recovery).unwrap().is_empty());
+        assert!(manager.scheduled_recoveries().is_empty());
+
+        let response = manager.receive_message(&recovery).unwrap();
+        assert_eq!(response.len(), 1);
+        assert_eq!(response[0].channel, Channel::Warning("double recovery for person 7".into()));
+    }
+
+    #[test]
+    fn test_duration_is_configurable() {
+        // With the same seed, each sample scales with the mean duration.
+        let mut short = InfectionManager::new_with_duration(1.0);
+        let mut long  = InfectionManager::new_with_duration(10.0);
+        let infected  = Message::make_person_status(1, 7, 0, InfectionStatus::Infected, Some(0.0.into()));
+        short.receive_message(&infected).unwrap();
+        long.receive_message(&infected).unwrap();
+
+        let short_duration = short.scheduled_recoveries()[&7].0;
+        let long_duration  = long.scheduled_recoveries()[&7].0;
+        assert!((long_duration - 10.0 * short_duration).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_duration_per_person() {
+        // Odd-numbered people stay infected ten times as long, on average.
+        let mut stratified = InfectionManager::new()
+            .with_duration_fn(Box::new(|person_id| if person_id % 2 == 1 { 10.0 } else { 1.0 }));
+        let mut short      = InfectionManager::new_with_duration(1.0);
+        let mut long       = InfectionManager::new_with_duration(10.0);
+        for person_id in [6, 7] {
+            let infected = Message::make_person_status(1, person_id, 0, InfectionStatus::Infected, Some(0.0.into()));
+            stratified.receive_message(&infected).unwrap();
+            short.receive_message(&infected).unwrap();
+            long.receive_message(&infected).unwrap();
+        }
+
+        // With the same seed, the stratified manager draws what the uniform ones draw.
+        let recoveries = stratified.scheduled_recoveries();
+        assert!((recoveries[&6].0 - short.scheduled_recoveries()[&6].0).abs() < 1e-9);
+        assert!((recoveries[&7].0 - long.scheduled_recoveries()[&7].0).abs() < 1e-9);
+    }
+}
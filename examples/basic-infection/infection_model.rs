@@ -0,0 +1,96 @@
+/*!
+
+An `InfectionModel` decides how long the `TransmissionManager` waits between infection
+attempts. By default the `TransmissionManager` uses a constant force of infection; a model lets
+the force of infection depend on the simulated time, e.g. to represent seasonality.
+
+*/
+
+use std::f64::consts::PI;
+
+use rand::prelude::SmallRng;
+use rand_distr::{Distribution, Exp};
+
+use actor_model::timeline::Time;
+
+/// How long to wait for the next infection attempt, given the current time.
+pub trait InfectionModel {
+    /// Samples the time from `now` until the next infection attempt, for a population of one.
+    fn next_attempt_delta(&self, now: Time, rng: &mut SmallRng) -> f64;
+}
+
+/// An `InfectionModel` that the `TransmissionManager` can own. With the `send` feature it must be
+/// `Send`, like the actor that owns it.
+#[cfg(not(feature = "send"))]
+pub type BoxedInfectionModel = Box<dyn InfectionModel>;
+#[cfg(feature = "send")]
+pub type BoxedInfectionModel = Box<dyn InfectionModel + Send>;
+
+/// A force of infection that rises and falls with the seasons:
+/// `foi(t) = base_foi * (1 + amplitude * sin(2π t / period))`, which peaks a quarter of the way
+/// into each period. Each delay is sampled at the force of infection when it is drawn, which
+/// is a good approximation as long as delays are short compared to `period`.
+#[allow(dead_code)]
+pub struct SeasonalFOI {
+    base_foi : f64,
+    amplitude: f64,
+    period   : f64,
+}
+
+#[allow(dead_code)]
+impl SeasonalFOI {
+    pub fn new(base_foi: f64, amplitude: f64, period: f64) -> Self {
+        assert!(base_foi > 0.0, "base_foi must be positive");
+        // At an amplitude of 1 or more the force of infection would reach 0 or less.
+        assert!((0.0..1.0).contains(&amplitude), "amplitude must be at least 0 and less than 1");
+        assert!(period > 0.0, "period must be positive");
+
+        SeasonalFOI { base_foi, amplitude, period }
+    }
+
+    /// The force of infection at `time`.
+    pub fn foi(&self, time: Time) -> f64 {
+        self.base_foi * (1.0 + self.amplitude * (2.0 * PI * time.0 / self.period).sin())
+    }
+}
+
+impl InfectionModel for SeasonalFOI {
+    fn next_attempt_delta(&self, now: Time, rng: &mut SmallRng) -> f64 {
+        Exp::new(self.foi(now)).unwrap().sample(rng)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use ordered_float::OrderedFloat;
+    use rand::SeedableRng;
+    use super::*;
+
+    #[test]
+    fn test_seasonal_peak_and_trough() {
+        let seasonal = SeasonalFOI::new(0.1, 0.5, 100.0);
+
+        assert!((seasonal.foi(OrderedFloat(0.0)) - 0.1).abs() < 1e-12);
+        assert!((seasonal.foi(OrderedFloat(25.0)) - 0.15).abs() < 1e-12);
+        assert!((seasonal.foi(OrderedFloat(75.0)) - 0.05).abs() < 1e-12);
+        // The season repeats.
+        assert!((seasonal.foi(OrderedFloat(125.0)) - 0.15).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_attempts_are_more_frequent_at_the_peak() {
+        let seasonal  = SeasonalFOI::new(0.1, 0.5, 100.0);
+        let mut rng   = SmallRng::seed_from_u64(42);
+        let mean_wait = |now: f64, rng: &mut SmallRng| {
+            (0..10_000).map(|_| seasonal.next_attempt_delta(OrderedFloat(now), rng)).sum::<f64>() / 10_000.0
+        };
+
+        let peak   = mean_wait(25.0, &mut rng);
+        let trough = mean_wait(75.0, &mut rng);
+
+        // The mean waits are 1 / 0.15 and 1 / 0.05.
+        assert!((peak - 1.0 / 0.15).abs() < 0.5);
+        assert!((trough - 1.0 / 0.05).abs() < 1.0);
+    }
+}
@@ -0,0 +1,92 @@
+/*!
+
+An `InterventionManager` models a time-based intervention, such as a food recall, that cuts
+the force of infection part way through the epidemic. It schedules its intervention when it
+is registered, and when the intervention's timeline event fires it tells the
+`TransmissionManager` the new force of infection.
+
+*/
+
+use actor_model::{
+    actor::{Actor, ActorHandle},
+    timeline::Time,
+};
+
+use crate::{
+    message::{ActorResult, Registration, Channel, Envelope, Message, Topic},
+    FOI,
+};
+
+pub struct InterventionManager {
+    handle           : ActorHandle,
+    intervention_time: Time,
+    /// The fraction by which the intervention reduces the force of infection, between 0 and 1.
+    foi_reduction    : f64,
+}
+
+impl InterventionManager {
+    pub fn new(intervention_time: Time, foi_reduction: f64) -> Self {
+        assert!((0.0..=1.0).contains(&foi_reduction), "foi_reduction must be between 0 and 1");
+
+        InterventionManager {
+            handle: 0,
+            intervention_time,
+            foi_reduction,
+        }
+    }
+}
+
+impl Actor<Message, Topic> for InterventionManager {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+        let messages = match *envelope {
+            Envelope {
+                channel: Channel::TimelineEvent,
+                message: Some(Message::Intervene),
+                ..
+            } => {
+                vec![Message::make_update_foi(self.handle, FOI * (1.0 - self.foi_reduction))]
+            }
+
+            _ => {
+                vec![]
+            }
+        };
+
+        #[cfg(feature = "print_messages")]
+        for message in &messages {
+            println!("INTERVENTION MANAGER: {:?}", message);
+        }
+
+        Ok(messages)
+    }
+
+    fn register(&mut self, handle: ActorHandle) -> Registration {
+        self.handle = handle;
+
+        Ok((
+            vec![Channel::TimelineEvent],
+            vec![Message::make_schedule_intervention(handle, self.intervention_time)]
+        ))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use actor_model::{harness::ActorTestHarness, rc_cell};
+    use ordered_float::OrderedFloat;
+    use crate::message::RcEnvelope;
+    use super::*;
+
+    #[test]
+    fn test_intervention_reduces_foi() {
+        let mut harness = ActorTestHarness::new(rc_cell!(InterventionManager::new(OrderedFloat(10.0), 0.25)));
+        assert_eq!(harness.initial_messages()[0].time, OrderedFloat(10.0));
+
+        let intervention = Envelope::new(0, Channel::TimelineEvent, Some(Message::Intervene), Some(OrderedFloat(10.0)));
+        let response     = harness.send(RcEnvelope::new(intervention)).unwrap();
+
+        assert_eq!(response.len(), 1);
+        assert_eq!(response[0].message, Some(Message::UpdateFOI(OrderedFloat(FOI * 0.75))));
+    }
+}
@@ -1,57 +1,93 @@
-#![feature(assert_matches)]
 // #![feature(trait_alias)]
 /*!
 
 The `basic-infection` example model in the Actor pattern.
 
 */
+mod allocation_counter;
 mod people;
 mod message;
 mod infection_manager;
 mod transmission_manager;
+mod network_transmission_manager;
 mod incidence_reporter;
+mod terminator;
+mod vaccination_manager;
+mod intervention_manager;
+mod infection_model;
+#[cfg(feature = "print_messages")]
+mod message_tracer;
 
-use std::{
-    rc::Rc,
-    cell::RefCell
-};
-use std::convert::Into;
 use ordered_float::OrderedFloat;
 use actor_model::{
     router::Router as GenericRouter,
-    actor::{RcActor as GenericRcActor},
-    rc_cell,
-    rccell::RcCell
+    actor::{RcActor as GenericRcActor, RegistrationError},
+    rc_cell
 };
 use actor_model::timeline::Time;
 use crate::{
     message::{Message, Topic},
     people::Population
 };
+use crate::allocation_counter::CountingAllocator;
 use crate::incidence_reporter::IncidenceReporter;
 use crate::infection_manager::InfectionManager;
 use crate::transmission_manager::TransmissionManager;
+use crate::network_transmission_manager::NetworkTransmissionManager;
+use crate::terminator::TerminatorActor;
+use crate::vaccination_manager::VaccinationManager;
+use crate::intervention_manager::InterventionManager;
 
 // Trait aliases haven't landed yet.
 // pub(crate) trait Actor  = GenericActor<Message, Topic>;
+#[allow(dead_code)]
 pub(crate) type RcActor = GenericRcActor<Message, Topic>;
 pub(crate) type Router  = GenericRouter<Message, Topic>;
 
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::new();
+
 
 
-static POPULATION        : u32 = 1000;
-static SEED              : u32 = 123;
-static MAX_TIME          : Time = OrderedFloat(303.0);
-static FOI               : f64 = 0.1;
-static INFECTION_DURATION: f64 = 5.0;
+static POPULATION          : u32 = 1000;
+#[allow(dead_code)]
+static SEED                : u32 = 123;
+static MAX_TIME            : Time = OrderedFloat(303.0);
+static FOI                 : f64 = 0.1;
+static INFECTION_DURATION  : f64 = 5.0;
+static VACCINATION_COVERAGE: f64 = 0.1;
+static INTERVENTION_TIME   : Time = OrderedFloat(30.0);
+static FOI_REDUCTION       : f64 = 0.5;
 
 
-fn main() {
+fn main() -> Result<(), RegistrationError> {
     let mut context = Router::new();
-    context.add_actor(rc_cell!(InfectionManager::new()));
-    context.add_actor(rc_cell!(TransmissionManager::new()));
-    context.add_actor(rc_cell!(IncidenceReporter::new("./examples/basic-infection/incidence_report.csv")));
-    context.add_actor(rc_cell!(Population::new(POPULATION)));
+    // Print each message with the ID of the message that caused it.
+    #[cfg(feature = "print_messages")]
+    {
+        context = context.with_causal_tracking(true);
+        context.add_observer(rc_cell!(message_tracer::MessageTracer))?;
+    }
+    context.add_actor(rc_cell!(InfectionManager::new()))?;
+    // Pass the path of a contact graph (a CSV edge list) to spread infection over the
+    // network instead of with a constant force of infection.
+    match std::env::args().nth(1) {
+        Some(contact_graph) => context.add_actor(rc_cell!(NetworkTransmissionManager::new(&contact_graph)))?,
+        None                => context.add_actor(rc_cell!(TransmissionManager::new()))?,
+    };
+    context.add_actor(rc_cell!(IncidenceReporter::new("./examples/basic-infection/incidence_report.csv")))?;
+    context.add_actor(rc_cell!(Population::new(POPULATION)))?;
+    context.add_actor(rc_cell!(TerminatorActor::new()))?;
+    context.add_actor(rc_cell!(VaccinationManager::new(POPULATION, VACCINATION_COVERAGE)))?;
+    context.add_actor(rc_cell!(InterventionManager::new(INTERVENTION_TIME, FOI_REDUCTION)))?;
+    let setup_allocations = ALLOCATOR.allocations();
 
     context.run();
+    eprintln!(
+        "Heap allocations: {} during setup, {} during the run",
+        setup_allocations,
+        ALLOCATOR.allocations() - setup_allocations
+    );
+
+    Ok(())
 }
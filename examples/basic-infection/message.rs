@@ -8,7 +8,11 @@ in the form of an implementor of `MessagePayload`.
 */
 
 use actor_model::{
-  actor::ActorHandle,
+  actor::{
+    ActorHandle,
+    ActorResult  as GenericActorResult,
+    Registration as GenericRegistration
+  },
   message::{
     Channel    as GenericChannel,
     Envelope   as GenericEnvelope,
@@ -17,12 +21,17 @@ use actor_model::{
   timeline::Time
 };
 
-use crate::people::{InfectionStatus, PersonID};
+use ordered_float::OrderedFloat;
+
+use crate::people::{InfectionStatus, PersonID, PersonId, SubPopulationID};
 
-// We "concretize" the generic types for this model.
+// We "concretize" the generic types for this model. Only the `Population` is generic over the
+// type of `PersonID`; everything else uses the default.
 pub(crate) type Channel    = GenericChannel<Topic>;
-pub(crate) type Envelope   = GenericEnvelope<Message  , Topic>;
-pub(crate) type RcEnvelope = GenericRcEnvelope<Message, Topic>;
+pub(crate) type Envelope<ID = PersonID>     = GenericEnvelope<Message<ID>  , Topic>;
+pub(crate) type RcEnvelope<ID = PersonID>   = GenericRcEnvelope<Message<ID>, Topic>;
+pub(crate) type ActorResult<ID = PersonID>  = GenericActorResult<Message<ID>, Topic>;
+pub(crate) type Registration<ID = PersonID> = GenericRegistration<Message<ID>, Topic>;
 
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -32,83 +41,116 @@ pub enum Topic {
   PersonStatus,       // Send the status of a person
   ChangePersonStatus, // Change the status of a person
   RequestPersonStatus,
-  PopulationReport    // Send/Query the population report
+  PopulationReport,   // Send/Query the population report
+  AllRecovered,       // Everyone has recovered, so the epidemic is over
+  RequestAge,         // Query the age of a person
+  Age,                // Send the age of a person
+  ChangePopulation,   // Add or remove a person
+  PopulationChanged,  // A person has been added or removed
+
+  // Messages related to `InfectionManager`
+  RecoveryScheduled,  // A person's recovery has been put on the timeline
+
+  // Messages related to `TransmissionManager`
+  UpdateFOI,          // Change the force of infection
 }
 
 
+/// `ID` identifies people. It is `PersonID` except in models too large for it.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub enum Message {
+pub enum Message<ID = PersonID> {
   /// Used to hold status change for timeline events and direct requests for status change
-  /// and to respond to queries for a person's current status.
-  PersonStatus(PersonID, InfectionStatus),
-  RequestPersonStatus(PersonID),
+  /// and to respond to queries for a person's current status. The `Population` fills in the
+  /// person's sub-population; in a request for a status change it is ignored.
+  PersonStatus(ID, SubPopulationID, InfectionStatus),
+  RequestPersonStatus(ID),
   PopulationReport{
     susceptible: u32,
     infected   : u32,
     recovered  : u32,
   },
+  /// The part of a `PopulationReport` for one sub-population, sent after it when the
+  /// population has more than one.
+  SubPopulationReport{
+    sub_population: SubPopulationID,
+    susceptible   : u32,
+    infected      : u32,
+    recovered     : u32,
+  },
   AttemptInfection,
+  AttemptNetworkInfection,
+  AddPerson,
+  RemovePerson(ID),
+  PersonAdded(ID),
+  PersonRemoved(ID),
+  AgeRequest(ID),
+  AgeResponse(ID, u8),
+  /// The person's recovery is scheduled for the given time.
+  RecoveryScheduled(ID, Time),
+  /// Asks the `TransmissionManager` when its next infection attempt is scheduled.
+  NextAttemptTimeRequest,
+  NextAttemptTimeResponse(Time),
+  /// The timeline event on which the `InterventionManager` intervenes.
+  Intervene,
+  /// Sets the `TransmissionManager`'s force of infection. An `OrderedFloat` so that `Message`
+  /// stays `Eq` and `Hash`.
+  UpdateFOI(OrderedFloat<f64>),
 }
 
-impl Message {
-  // Convenience methods
+impl<ID: PersonId> Message<ID> {
+  // Convenience methods for the messages the `Population` sends
 
   #[inline(always)]
-  pub fn make_person_status_change(actor_handle: ActorHandle, person_id: PersonID, infection_status: InfectionStatus, time: Time) -> RcEnvelope {
+  pub fn make_person_status(
+    actor_handle: ActorHandle,
+    person_id: ID,
+    sub_population: SubPopulationID,
+    infection_status: InfectionStatus,
+    time: Option<Time>
+  ) -> RcEnvelope<ID> {
     RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Topic(Topic::ChangePersonStatus),
-        message: Some(
-          Message::PersonStatus(person_id, infection_status)
-        ),
-        time   : Some(time),
-      }
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PersonStatus),
+        Some(Message::PersonStatus(person_id, sub_population, infection_status)),
+        time
+      )
     )
   }
 
   #[inline(always)]
-  pub fn make_person_status_request(actor_handle: ActorHandle, person_id: PersonID) -> RcEnvelope {
+  pub fn make_person_added(actor_handle: ActorHandle, person_id: ID) -> RcEnvelope<ID> {
     RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Topic(Topic::RequestPersonStatus),
-        message: Some(
-          Message::RequestPersonStatus(person_id)
-        ),
-        time   : None,
-      }
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PopulationChanged),
+        Some(Message::PersonAdded(person_id)),
+        None
+      )
     )
   }
 
   #[inline(always)]
-  pub fn make_person_status(
-    actor_handle: ActorHandle,
-    person_id: PersonID,
-    infection_status: InfectionStatus,
-    time: Option<Time>
-  ) -> RcEnvelope {
-    RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Topic(Topic::PersonStatus),
-        message: Some(
-          Message::PersonStatus(person_id, infection_status)
-        ),
-        time,
-      }
+  pub fn make_person_removed(actor_handle: ActorHandle, person_id: ID) -> RcEnvelope<ID> {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PopulationChanged),
+        Some(Message::PersonRemoved(person_id)),
+        None
+      )
     )
   }
 
   #[inline(always)]
-  pub fn make_population_report_request(actor_handle: ActorHandle) -> RcEnvelope {
+  pub fn make_age_response(actor_handle: ActorHandle, person_id: ID, age: u8) -> RcEnvelope<ID> {
     RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Topic(Topic::PopulationReport),
-        message: None,
-        time   : None,
-      }
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::Age),
+        Some(Message::AgeResponse(person_id, age)),
+        None
+      )
     )
   }
 
@@ -118,45 +160,291 @@ impl Message {
     susceptible: u32,
     infected: u32,
     recovered: u32
-  ) -> RcEnvelope {
-    RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Topic(Topic::PopulationReport),
-        message: Some(
-          Message::PopulationReport {
-            susceptible,
-            infected,
-            recovered,
-          }
-        ),
-        time  : None,
-      }
+  ) -> RcEnvelope<ID> {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PopulationReport),
+        Some(Message::PopulationReport { susceptible, infected, recovered }),
+        None
+      )
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_sub_population_report(
+    actor_handle: ActorHandle,
+    sub_population: SubPopulationID,
+    susceptible: u32,
+    infected: u32,
+    recovered: u32
+  ) -> RcEnvelope<ID> {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PopulationReport),
+        Some(Message::SubPopulationReport { sub_population, susceptible, infected, recovered }),
+        None
+      )
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_all_recovered(actor_handle: ActorHandle) -> RcEnvelope<ID> {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::AllRecovered),
+        None,
+        None
+      )
+    )
+  }
+}
+
+impl Message {
+  // Convenience methods
+
+  /// The `Population` knows each person's sub-population, so the request leaves it as 0.
+  #[inline(always)]
+  pub fn make_person_status_change(actor_handle: ActorHandle, person_id: PersonID, infection_status: InfectionStatus, time: Time) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::ChangePersonStatus),
+        Some(Message::PersonStatus(person_id, 0, infection_status)),
+        Some(time)
+      )
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_person_status_request(actor_handle: ActorHandle, person_id: PersonID) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::RequestPersonStatus),
+        Some(Message::RequestPersonStatus(person_id)),
+        None
+      )
+    )
+  }
+
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_add_person(actor_handle: ActorHandle) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::ChangePopulation),
+        Some(Message::AddPerson),
+        None
+      )
+    )
+  }
+
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_remove_person(actor_handle: ActorHandle, person_id: PersonID) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::ChangePopulation),
+        Some(Message::RemovePerson(person_id)),
+        None
+      )
+    )
+  }
+
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_age_request(actor_handle: ActorHandle, person_id: PersonID) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::RequestAge),
+        Some(Message::AgeRequest(person_id)),
+        None
+      )
+    )
+  }
+
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_population_report_request(actor_handle: ActorHandle) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::PopulationReport),
+        None,
+        None
+      )
     )
   }
 
   #[inline(always)]
   pub fn make_schedule_attempt_infection(actor_handle: ActorHandle, time: Time) -> RcEnvelope {
     RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::ScheduleEvent,
-        message: Some(Message::AttemptInfection),
-        time   : Some(time),
-      }
+      Envelope::new(
+        actor_handle,
+        Channel::ScheduleEvent,
+        Some(Message::AttemptInfection),
+        Some(time)
+      )
+        .with_description("attempt infection")
+        .with_name("attempt_infection")
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_schedule_attempt_network_infection(actor_handle: ActorHandle, time: Time) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::ScheduleEvent,
+        Some(Message::AttemptNetworkInfection),
+        Some(time)
+      )
+        .with_description("attempt network infection")
+        .with_name("attempt_network_infection")
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_recovery_scheduled(actor_handle: ActorHandle, person_id: PersonID, time: Time) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::RecoveryScheduled),
+        Some(Message::RecoveryScheduled(person_id, time)),
+        None
+      )
+    )
+  }
+
+  /// Asks the router for the current time, which it answers on `Channel::Time`.
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_time_request(actor_handle: ActorHandle) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Time,
+        None,
+        None
+      )
+    )
+  }
+
+  #[allow(dead_code)]
+  #[inline(always)]
+  pub fn make_next_attempt_time_request(actor_handle: ActorHandle) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Time,
+        Some(Message::NextAttemptTimeRequest),
+        None
+      )
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_next_attempt_time_response(actor_handle: ActorHandle, time: Time) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Time,
+        Some(Message::NextAttemptTimeResponse(time)),
+        Some(time)
+      )
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_schedule_intervention(actor_handle: ActorHandle, time: Time) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::ScheduleEvent,
+        Some(Message::Intervene),
+        Some(time)
+      )
+        .with_description("intervention")
+        .with_name("intervention")
+    )
+  }
+
+  #[inline(always)]
+  pub fn make_update_foi(actor_handle: ActorHandle, foi: f64) -> RcEnvelope {
+    RcEnvelope::new(
+      Envelope::new(
+        actor_handle,
+        Channel::Topic(Topic::UpdateFOI),
+        Some(Message::UpdateFOI(OrderedFloat(foi))),
+        None
+      )
     )
   }
 
   #[inline(always)]
   pub fn make_stop_message(actor_handle: ActorHandle) -> RcEnvelope {
     RcEnvelope::new(
-      Envelope {
-        from   : actor_handle,
-        channel: Channel::Stop,
-        message: None,
-        time   : None,
-      }
+      Envelope::new(
+        actor_handle,
+        Channel::Stop,
+        None,
+        None
+      )
     )
   }
 
 }
+
+
+#[cfg(test)]
+mod test {
+  use actor_model::{actor::{Actor, ActorHandle}, rc_cell, rccell::RcCell};
+  use crate::Router;
+  use super::*;
+
+  /// Records the time in each of the router's answers to a `Channel::Time` request.
+  struct TimeRecorder {
+    times: RcCell<Vec<Time>>,
+  }
+
+  impl Actor<Message, Topic> for TimeRecorder {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+      // The request itself is also delivered on `Channel::Time`, but only the answer is timed.
+      if let Envelope { channel: Channel::Time, message: None, is_timed_event: true, time, .. } = *envelope {
+        self.times.borrow_mut().push(time);
+      }
+      Ok(vec![])
+    }
+
+    fn register(&mut self, _handle: ActorHandle) -> Registration {
+      Ok((vec![Channel::Time], vec![]))
+    }
+  }
+
+  #[test]
+  fn test_time_request() {
+    let times       = RcCell::new(vec![]);
+    let mut context = Router::new();
+    context.add_actor(rc_cell!(TimeRecorder { times: times.clone() })).unwrap();
+
+    // Advance the timeline to 5.0 with an event nobody listens for.
+    let event = RcEnvelope::new(Envelope::new(0, Channel::General, None, Some(5.0.into())));
+    context.push_timeline_event(5.0.into(), event);
+    context.run();
+    assert_eq!(context.now(), Time::from(5.0));
+
+    // The router doesn't deliver its answer right away. It enqueues it, so we run again.
+    let responses = context.silent_route(Message::make_time_request(0));
+    assert!(responses.is_empty());
+    assert!(times.borrow().is_empty());
+    context.run();
+
+    assert_eq!(*times.borrow(), vec![Time::from(5.0)]);
+  }
+}
@@ -0,0 +1,79 @@
+/*!
+
+The `MessageTracer` prints every message it hears along with its ID and the ID of the message
+that caused it, e.g. `[msg_id:5 correl:3]`, so a `PersonStatus` can be traced back through the
+`ChangePersonStatus` that caused it to the `AttemptInfection` event that caused that. It is an
+observer, so it can't affect the simulation. The `Router` only assigns the IDs with causal
+tracking turned on (see `Router::with_causal_tracking()`).
+
+*/
+
+use actor_model::actor::{Actor, ActorHandle};
+
+use crate::message::{ActorResult, Registration, Channel, Envelope, Topic, Message};
+
+pub struct MessageTracer;
+
+impl MessageTracer {
+    /// The envelope's IDs as `[msg_id:5 correl:3]`, with `-` for an ID it doesn't have.
+    pub fn trace_ids(envelope: &Envelope) -> String {
+        let id = |id: Option<u64>| id.map_or("-".to_string(), |id| id.to_string());
+        format!("[msg_id:{} correl:{}]", id(envelope.message_id), id(envelope.correlation_id))
+    }
+}
+
+impl Actor<Message, Topic> for MessageTracer {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+        println!("TRACE {} {:?}: {:?}", Self::trace_ids(envelope), envelope.channel, envelope.message);
+
+        Ok(vec![])
+    }
+
+    fn register(&mut self, _handle: ActorHandle) -> Registration {
+        let subscriptions = vec![
+            Channel::TimelineEvent,
+            Channel::BulkTopic(vec![
+                Topic::PersonStatus,
+                Topic::ChangePersonStatus,
+                Topic::RequestPersonStatus,
+                Topic::PopulationReport,
+                Topic::AllRecovered,
+                Topic::RequestAge,
+                Topic::Age,
+                Topic::ChangePopulation,
+                Topic::PopulationChanged,
+                Topic::RecoveryScheduled,
+                Topic::UpdateFOI,
+            ]),
+        ];
+
+        Ok((subscriptions, vec![]))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use actor_model::rc_cell;
+    use crate::{people::{InfectionStatus, Population}, Router};
+    use super::*;
+
+    #[test]
+    fn test_responses_carry_their_cause() {
+        let mut context = Router::new().with_causal_tracking(true);
+        context.add_actor(rc_cell!(Population::new(2))).unwrap();
+        context.add_observer(rc_cell!(MessageTracer)).unwrap();
+
+        let change   = Message::make_person_status_change(0, 1, InfectionStatus::Infected, 1.0.into());
+        let response = context.silent_route(change);
+
+        let status = &response[0];
+        assert_eq!(status.message, Some(Message::PersonStatus(1, 0, InfectionStatus::Infected)));
+        assert!(status.correlation_id.is_some());
+        assert_eq!(
+            MessageTracer::trace_ids(status),
+            format!("[msg_id:{} correl:{}]", status.message_id.unwrap(), status.correlation_id.unwrap())
+        );
+        assert_eq!(MessageTracer::trace_ids(&Envelope::new(0, Channel::General, None, None)), "[msg_id:- correl:-]");
+    }
+}
@@ -0,0 +1,243 @@
+/*!
+
+A `NetworkTransmissionManager` spreads infection over a contact network rather than with a
+constant force of infection. It plays the same role as the `TransmissionManager`, but
+infection can only pass from an infected person to one of their contacts.
+
+The network is read from a CSV edge list with a `source,target` header when the simulation
+starts. Edges are undirected.
+
+Each infected person makes contact attempts at rate `FOI`. An attempt has two phases:
+
+ 1. We select an infected person at random, select one of their contacts at random, and
+    query the contact's status.
+ 2. When we receive the contact's status, if they are susceptible we change their status
+    to infected.
+
+When nobody is infected, no further attempts are scheduled and the simulation winds down.
+
+*/
+
+use std::{
+    collections::HashMap,
+    path::PathBuf
+};
+
+use rand::{prelude::SmallRng, seq::IndexedRandom, SeedableRng};
+use rand_distr::{Distribution, Exp};
+use serde::Deserialize;
+
+use actor_model::{
+    actor::{Actor, ActorError, ActorHandle},
+    timeline::Time,
+};
+
+use crate::{
+    message::{
+        ActorResult,
+        Registration,
+        Channel,
+        Envelope,
+        Message,
+        RcEnvelope,
+        Topic
+    },
+    people::{InfectionStatus, PersonID},
+    FOI,
+    MAX_TIME,
+};
+
+#[derive(Deserialize)]
+struct Edge {
+    source: PersonID,
+    target: PersonID,
+}
+
+pub struct NetworkTransmissionManager {
+    handle              : ActorHandle,
+    rng                 : SmallRng,
+    exp_dist            : Exp<f64>,
+    contact_graph       : PathBuf,
+    neighbors           : HashMap<PersonID, Vec<PersonID>>,
+    infected            : Vec<PersonID>,
+    current_attempt_time: Time,
+    selected_person     : Option<PersonID>,
+}
+
+impl NetworkTransmissionManager {
+    pub fn new(contact_graph: &str) -> Self {
+        NetworkTransmissionManager {
+            handle              : 0,
+            rng                 : SmallRng::seed_from_u64(42),
+            exp_dist            : Exp::new(FOI).unwrap(),
+            contact_graph       : PathBuf::from(contact_graph),
+            neighbors           : HashMap::new(),
+            infected            : vec![],
+            current_attempt_time: 0.0.into(),
+            selected_person     : None,
+        }
+    }
+
+    /// Reads the edge list into an adjacency list.
+    fn load_contact_graph(&mut self) -> csv::Result<()> {
+        let mut reader = csv::Reader::from_path(&self.contact_graph)?;
+        for edge in reader.deserialize() {
+            let Edge{ source, target } = edge?;
+            self.neighbors.entry(source).or_default().push(target);
+            self.neighbors.entry(target).or_default().push(source);
+        }
+        Ok(())
+    }
+
+    /// Infects a random member of the network and schedules the first contact attempt.
+    fn seed_infection(&mut self, time: Time) -> Vec<RcEnvelope> {
+        let mut people: Vec<PersonID> = self.neighbors.keys().copied().collect();
+        // Sort so the choice only depends on the seed, not on `HashMap` iteration order.
+        people.sort_unstable();
+
+        match people.choose(&mut self.rng) {
+            Some(&index_case) => vec![
+                Message::make_person_status_change(self.handle, index_case, InfectionStatus::Infected, time),
+                Message::make_schedule_attempt_network_infection(self.handle, time),
+            ],
+            None => vec![],
+        }
+    }
+
+    /// Selects a contact of a random infected person and requests their infection status.
+    /// Also schedules the next attempt, if anyone is still infected and there is time left.
+    fn query_random_contact(&mut self, time: Time) -> Vec<RcEnvelope> {
+        let mut messages = vec![];
+
+        let Some(&infector) = self.infected.choose(&mut self.rng) else {
+            // Nobody left to spread the infection.
+            return messages;
+        };
+
+        // The population-level attempt rate is proportional to the number of infected people.
+        let next_attempt_time = time + self.exp_dist.sample(&mut self.rng) / (self.infected.len() as f64);
+        if next_attempt_time <= MAX_TIME {
+            messages.push(Message::make_schedule_attempt_network_infection(self.handle, next_attempt_time));
+        }
+
+        if let Some(&contact) = self.neighbors.get(&infector).and_then(|contacts| contacts.choose(&mut self.rng)) {
+            self.current_attempt_time = time;
+            self.selected_person      = Some(contact);
+            messages.push(Message::make_person_status_request(self.handle, contact));
+        }
+
+        messages
+    }
+}
+
+impl Actor<Message, Topic> for NetworkTransmissionManager {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+        let messages = match *envelope {
+            Envelope {
+                channel: Channel::SimulationStarted,
+                time,
+                ..
+            } => {
+                if let Err(error) = self.load_contact_graph() {
+                    return Err(ActorError::Fatal(format!("failed to read contact graph: {}", error)));
+                }
+                self.seed_infection(time)
+            }
+
+            // Keep track of who is infected. Only a transition is a timed event.
+            Envelope {
+                channel       : Channel::Topic(Topic::PersonStatus),
+                message       : Some(Message::PersonStatus(person_id, _, infection_status)),
+                is_timed_event: true,
+                ..
+            } => {
+                match infection_status {
+                    InfectionStatus::Infected  => self.infected.push(person_id),
+                    InfectionStatus::Recovered => self.infected.retain(|&infected| infected != person_id),
+                    InfectionStatus::Susceptible => {}
+                }
+                vec![]
+            }
+
+            // The status of the contact we selected
+            Envelope {
+                channel       : Channel::Topic(Topic::PersonStatus),
+                message       : Some(Message::PersonStatus(person_id, _, infection_status)),
+                is_timed_event: false,
+                ..
+            } if Some(person_id) == self.selected_person => {
+                self.selected_person = None;
+                if infection_status == InfectionStatus::Susceptible {
+                    vec![Message::make_person_status_change(
+                        self.handle,
+                        person_id,
+                        InfectionStatus::Infected,
+                        self.current_attempt_time,
+                    )]
+                } else {
+                    vec![]
+                }
+            }
+
+            Envelope {
+                channel: Channel::TimelineEvent,
+                message: Some(Message::AttemptNetworkInfection),
+                time,
+                ..
+            } => {
+                self.query_random_contact(time)
+            }
+
+            _ => {
+                /* pass */
+                vec![]
+            }
+        };
+
+        #[cfg(feature = "print_messages")]
+        for message in &messages {
+            println!("NETWORK TRANSMISSION MANAGER: {:?}", message);
+        }
+
+        Ok(messages)
+    }
+
+    fn register(&mut self, handle: ActorHandle) -> Registration {
+        self.handle = handle;
+
+        let subscriptions = vec![
+            Channel::SimulationStarted,
+            Channel::Topic(Topic::PersonStatus),
+            Channel::TimelineEvent,
+        ];
+
+        // We have no messages to send until the contact graph is loaded.
+        Ok((subscriptions, vec![]))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use actor_model::rc_cell;
+    use crate::{people::Population, Router};
+    use super::*;
+
+    #[test]
+    fn test_infection_stays_on_network() {
+        // People 0-2 form a triangle. People 3 and 4 are not in the network.
+        let contact_graph = std::env::temp_dir().join("network_transmission_manager_contacts.csv");
+        fs::write(&contact_graph, "source,target\n0,1\n1,2\n2,0\n").unwrap();
+
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(NetworkTransmissionManager::new(contact_graph.to_str().unwrap()))).unwrap();
+        context.add_actor(rc_cell!(Population::new(5))).unwrap();
+        context.run();
+
+        for person_id in 3..5 {
+            let response = context.silent_route(Message::make_person_status_request(0, person_id));
+            assert_eq!(response[0].message, Some(Message::PersonStatus(person_id, 0, InfectionStatus::Susceptible)));
+        }
+    }
+}
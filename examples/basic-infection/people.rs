@@ -4,15 +4,20 @@ An `Actor` that tracks the status of the population. It's only job is:
  - report the status of a person
  - change the status of a person
  - report the count of people of each status
+ - add and remove people (births and deaths)
 
 */
 
+use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+
 use serde::{Deserialize, Serialize};
 
 use actor_model::actor::{Actor, ActorHandle};
 
 use crate::{
     message::{
+        ActorResult,
+        Registration,
         Channel,
         Envelope,
         Message,
@@ -23,7 +28,41 @@ use crate::{
 
 pub type PersonID = u32;
 
+/// A type that can identify people. `PersonID` is enough for the demos, but a national model
+/// needs a `u64`. The `Population` indexes its people by ID, so IDs convert to and from `usize`.
+/// (`u32` and `u64` don't implement `From<usize>` or `Into<usize>`, hence this trait.)
+pub trait PersonId: Copy + Debug + Hash + Eq + 'static {
+    /// The ID of the person at `index`. Panics if the ID type is too small.
+    fn from_index(index: usize) -> Self;
+
+    fn index(self) -> usize;
+}
+
+impl PersonId for u32 {
+    fn from_index(index: usize) -> Self {
+        u32::try_from(index).expect("too many people for a u32 person ID")
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl PersonId for u64 {
+    fn from_index(index: usize) -> Self {
+        index as u64
+    }
+
+    fn index(self) -> usize {
+        usize::try_from(self).expect("person ID does not fit in usize")
+    }
+}
+/// Identifies a group of people, e.g. a risk group, in a multi-group model.
+pub type SubPopulationID = u8;
+
+/// Serialized as its numeric discriminant, which keeps binary population snapshots compact.
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
 #[repr(u8)]
 pub enum InfectionStatus {
     Susceptible,
@@ -31,84 +70,228 @@ pub enum InfectionStatus {
     Recovered,
 }
 
+impl From<InfectionStatus> for u8 {
+    fn from(infection_status: InfectionStatus) -> u8 {
+        infection_status as u8
+    }
+}
 
-pub struct Population {
+impl TryFrom<u8> for InfectionStatus {
+    type Error = String;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(InfectionStatus::Susceptible),
+            1 => Ok(InfectionStatus::Infected),
+            2 => Ok(InfectionStatus::Recovered),
+            _ => Err(format!("invalid infection status code: {}", code)),
+        }
+    }
+}
+
+
+pub struct Population<ID: PersonId = PersonID> {
     // A real implementation wouldn't keep track of each individual. It would only need the counts.
-    people: Vec<InfectionStatus>,
+    /// The status of each person, indexed by `PersonID`, or `None` if the person has been
+    /// removed. Removing a person leaves a hole so that other people keep their IDs.
+    people: Vec<Option<InfectionStatus>>,
+    /// The age of each person, indexed by `PersonID`.
+    age_distribution: Vec<u8>,
+    /// The sub-population of each person, indexed by `PersonID`.
+    sub_populations: Vec<SubPopulationID>,
+    /// The number of sub-populations. Population reports are broken down by sub-population
+    /// when there is more than one.
+    sub_population_count: usize,
     handle: ActorHandle,
 
     susceptible: u32,
     infected   : u32,
     recovered  : u32,
+    _id        : PhantomData<ID>,
 }
 
-impl Population {
+impl<ID: PersonId> Population<ID> {
+    /// Creates a population in which everyone has age 0.
     #[inline(always)]
-    pub fn new(person_count: u32) -> Population {
-        let people: Vec<InfectionStatus> = vec![InfectionStatus::Susceptible; person_count as usize];
+    pub fn new(person_count: u32) -> Self {
+        Population::new_with_ages(person_count, vec![0; person_count as usize])
+    }
+
+    /// Creates a population for age-stratified models. `ages[i]` is the age of person `i`.
+    pub fn new_with_ages(person_count: u32, ages: Vec<u8>) -> Self {
+        assert_eq!(ages.len(), person_count as usize, "expected one age per person");
+
+        let people: Vec<Option<InfectionStatus>> = vec![Some(InfectionStatus::Susceptible); person_count as usize];
         Population {
             people,
-            handle     : 0, // set upon registration
-            susceptible: person_count,
-            infected   : 0,
-            recovered  : 0,
+            age_distribution: ages,
+            sub_populations : vec![0; person_count as usize],
+            sub_population_count: 1,
+            handle          : 0, // set upon registration
+            susceptible     : person_count,
+            infected        : 0,
+            recovered       : 0,
+            _id             : PhantomData,
         }
     }
 
+    /// Creates a population for multi-group models, e.g. with high- and low-risk groups.
+    /// Sub-population `i` has `counts[i]` people, who have consecutive IDs following those of
+    /// sub-population `i - 1`. Everyone has age 0.
+    #[allow(dead_code)]
+    pub fn new_with_subpopulations(counts: Vec<u32>) -> Self {
+        assert!(!counts.is_empty(), "expected at least one sub-population");
+        assert!(counts.len() <= SubPopulationID::MAX as usize + 1, "too many sub-populations");
+
+        let mut population = Population::new(counts.iter().sum());
+        population.sub_populations = counts
+            .iter()
+            .enumerate()
+            .flat_map(|(sub_population, &count)| std::iter::repeat_n(sub_population as SubPopulationID, count as usize))
+            .collect();
+        population.sub_population_count = counts.len();
+        population
+    }
+
+    /// The status of the person, or nothing if there is no such person.
     #[inline(always)]
-    fn get_person_status(&self, person_id: PersonID) -> RcEnvelope {
-        let status = self.people[person_id as usize];
+    fn get_person_status(&self, person_id: ID) -> Option<RcEnvelope<ID>> {
+        let status = (*self.people.get(person_id.index())?)?;
 
-        RcEnvelope::new(
-            Envelope {
-                from   : self.handle,
-                channel: Channel::Topic(Topic::PersonStatus),
-                message: Some(
-                    Message::PersonStatus(person_id, status)
-                ),
-                time  : None,
-            }
-        )
+        Some(Message::make_person_status(self.handle, person_id, self.get_sub_population(person_id)?, status, None))
+    }
 
+    /// The person's sub-population, or nothing if there is no such person.
+    #[inline(always)]
+    pub fn get_sub_population(&self, person_id: ID) -> Option<SubPopulationID> {
+        self.sub_populations.get(person_id.index()).copied()
+    }
 
+    #[inline(always)]
+    pub fn get_age(&self, person_id: ID) -> u8 {
+        self.age_distribution[person_id.index()]
     }
 
-    fn set_person_status(&mut self, person_id: PersonID, infection_status: InfectionStatus) {
-        // The previous status is implicit in this model. In other models it may not be.
-        // let previous_status: InfectionStatus = *self.people[person_id as usize];
-        self.people[person_id as usize] = infection_status;
+    /// The IDs of the people who are susceptible, in increasing order.
+    #[allow(dead_code)]
+    pub fn susceptible_ids(&self) -> impl Iterator<Item = ID> {
+        self.ids_with_status(InfectionStatus::Susceptible)
+    }
 
+    /// The IDs of the people who are infected, in increasing order.
+    #[allow(dead_code)]
+    pub fn infected_ids(&self) -> impl Iterator<Item = ID> {
+        self.ids_with_status(InfectionStatus::Infected)
+    }
+
+    /// The IDs of the people who have recovered, in increasing order.
+    #[allow(dead_code)]
+    pub fn recovered_ids(&self) -> impl Iterator<Item = ID> {
+        self.ids_with_status(InfectionStatus::Recovered)
+    }
+
+    /// The IDs of the people with the given status. Removed people are skipped.
+    fn ids_with_status(&self, infection_status: InfectionStatus) -> impl Iterator<Item = ID> {
+        self.people
+            .iter()
+            .enumerate()
+            .filter(move |(_, status)| **status == Some(infection_status))
+            .map(|(index, _)| ID::from_index(index))
+    }
+
+    /// Returns whether the counts changed, i.e. whether the person exists and was not
+    /// already in `infection_status`.
+    fn set_person_status(&mut self, person_id: ID, infection_status: InfectionStatus) -> bool {
+        // People usually move from susceptible to infected to recovered, but vaccination
+        // takes them straight from susceptible to recovered, so we use the previous status.
+        let Some(&Some(previous_status)) = self.people.get(person_id.index()) else {
+            // There is no such person, or the person has been removed.
+            return false;
+        };
+        if previous_status == infection_status {
+            return false;
+        }
+        self.people[person_id.index()] = Some(infection_status);
+
+        *self.count_mut(previous_status)  -= 1;
+        *self.count_mut(infection_status) += 1;
+        true
+    }
+
+    /// Whether the person exists and has not been removed.
+    fn contains(&self, person_id: ID) -> bool {
+        matches!(self.people.get(person_id.index()), Some(Some(_)))
+    }
+
+    /// Adds a susceptible person with age 0 in sub-population 0 and returns their ID.
+    fn add_person(&mut self) -> ID {
+        self.people.push(Some(InfectionStatus::Susceptible));
+        self.age_distribution.push(0);
+        self.sub_populations.push(0);
+        self.susceptible += 1;
+
+        ID::from_index(self.people.len() - 1)
+    }
+
+    /// Removes the person, returning whether there was such a person to remove.
+    fn remove_person(&mut self, person_id: ID) -> bool {
+        if !self.contains(person_id) {
+            return false;
+        }
+        let status = self.people[person_id.index()].take().unwrap();
+        *self.count_mut(status) -= 1;
+        // The hole stays, even at the end, so the ID is never reused. Otherwise a person added
+        // later would inherit, e.g., the removed person's scheduled recovery.
+
+        true
+    }
+
+    /// The number of people with the given status.
+    fn count_mut(&mut self, infection_status: InfectionStatus) -> &mut u32 {
         match infection_status {
-            InfectionStatus::Infected => {
-                self.susceptible -= 1;
-                self.infected    += 1;
-            }
-            InfectionStatus::Recovered => {
-                self.infected  -= 1;
-                self.recovered += 1;
-            }
-            InfectionStatus::Susceptible => {
-                // Should not happen.
-            }
+            InfectionStatus::Susceptible => &mut self.susceptible,
+            InfectionStatus::Infected    => &mut self.infected,
+            InfectionStatus::Recovered   => &mut self.recovered,
         }
     }
 
-    fn get_population_report(&self) -> RcEnvelope {
-        Message::make_population_report(
+    /// The population report, followed by one for each sub-population if there is more than one.
+    fn get_population_reports(&self) -> Vec<RcEnvelope<ID>> {
+        let mut reports = vec![Message::make_population_report(
             self.handle,
             self.susceptible,
             self.infected,
             self.recovered,
-        )
+        )];
+        if self.sub_population_count == 1 {
+            return reports;
+        }
+
+        let mut counts = vec![[0u32; 3]; self.sub_population_count];
+        for (status, &sub_population) in self.people.iter().zip(&self.sub_populations) {
+            if let Some(status) = status {
+                counts[sub_population as usize][*status as usize] += 1;
+            }
+        }
+        for (sub_population, [susceptible, infected, recovered]) in counts.into_iter().enumerate() {
+            reports.push(Message::make_sub_population_report(
+                self.handle,
+                sub_population as SubPopulationID,
+                susceptible,
+                infected,
+                recovered,
+            ));
+        }
+        reports
     }
 
     fn person_count(&self) -> usize {
-        self.people.len()
+        (self.susceptible + self.infected + self.recovered) as usize
     }
 }
 
-impl Actor<Message, Topic> for Population {
-    fn receive_message(&mut self, envelope: RcEnvelope) -> Vec<RcEnvelope> {
+impl<ID: PersonId> Actor<Message<ID>, Topic> for Population<ID> {
+    fn receive_message(&mut self, envelope: &Envelope<ID>) -> ActorResult<ID> {
         // In general, we have a method that responds to every message type we know how to answer.
 
         // There are two ways for a person's status to change: directly, and through a
@@ -118,28 +301,34 @@ impl Actor<Message, Topic> for Population {
 
             Envelope {
                 channel: Channel::Topic(Topic::ChangePersonStatus),
-                message: Some(Message::PersonStatus(person_id, infection_status)),
+                message: Some(Message::PersonStatus(person_id, _, infection_status)),
                 time,
                 ..
             }
             | Envelope {
                 channel: Channel::TimelineEvent,
-                message: Some(Message::PersonStatus(person_id, infection_status)),
+                message: Some(Message::PersonStatus(person_id, _, infection_status)),
                 time,
                 ..
             }
              => {
-                self.set_person_status(person_id, infection_status);
+                if !self.set_person_status(person_id, infection_status) {
+                    // Nothing changed, so there is nothing for listeners to react to.
+                    return Ok(vec![]);
+                }
+                // The status changed, so the person exists.
+                let sub_population = self.get_sub_population(person_id).expect("person should exist");
                 // We emit the person's new status after the change, thereby notifying any potential listeners.
                 let mut messages = vec![
-                    Message::make_person_status(self.handle, person_id, infection_status, time)
+                    Message::make_person_status(self.handle, person_id, sub_population, infection_status, Some(time))
                 ];
-                // Check if simulation is over.
+                // Announce when everyone has recovered. Whether that ends the simulation
+                // is up to whoever listens (see `TerminatorActor`).
                 if self.recovered == self.person_count() as u32 {
                     #[cfg(feature = "print_messages")]
                     println!("All people recovered.");
                     messages.push(
-                        Message::make_stop_message(self.handle)
+                        Message::make_all_recovered(self.handle)
                     );
                 }
                 messages
@@ -153,7 +342,40 @@ impl Actor<Message, Topic> for Population {
 
                 // This is a request for the status of a person. Note that the
                 // time will not be set, indicating this is not a transition.
-                vec![self.get_person_status(person_id)]
+                self.get_person_status(person_id).into_iter().collect()
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::ChangePopulation),
+                message: Some(Message::AddPerson),
+                ..
+            } => {
+                let person_id = self.add_person();
+                let mut messages = vec![Message::make_person_added(self.handle, person_id)];
+                messages.extend(self.get_population_reports());
+                messages
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::ChangePopulation),
+                message: Some(Message::RemovePerson(person_id)),
+                ..
+            } => {
+                if self.remove_person(person_id) {
+                    let mut messages = vec![Message::make_person_removed(self.handle, person_id)];
+                    messages.extend(self.get_population_reports());
+                    messages
+                } else {
+                    vec![]
+                }
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::RequestAge),
+                message: Some(Message::AgeRequest(person_id)),
+                ..
+            } if self.contains(person_id) => {
+                vec![Message::make_age_response(self.handle, person_id, self.get_age(person_id))]
             }
 
             Envelope {
@@ -162,7 +384,7 @@ impl Actor<Message, Topic> for Population {
                 ..
             } => {
                 // This is a request for a population report
-                vec![self.get_population_report()]
+                self.get_population_reports()
             }
 
             Envelope {
@@ -186,20 +408,24 @@ impl Actor<Message, Topic> for Population {
             println!("POPULATION: {:?}", message);
         }
 
-        messages
+        Ok(messages)
     }
 
 
-    fn register(&mut self, handle: ActorHandle) -> (Vec<Channel>, Vec<RcEnvelope>) {
+    fn register(&mut self, handle: ActorHandle) -> Registration<ID> {
         self.handle = handle;
 
-        let initial_population_report = self.get_population_report();
+        let initial_population_reports = self.get_population_reports();
         #[cfg(feature = "print_messages")]
-        println!("ROUTER/TIMELINE: {:?}", initial_population_report);
+        for report in &initial_population_reports {
+            println!("ROUTER/TIMELINE: {:?}", report);
+        }
 
         let subscriptions = vec![
             Channel::Topic(Topic::ChangePersonStatus),
             Channel::Topic(Topic::RequestPersonStatus),
+            Channel::Topic(Topic::RequestAge),
+            Channel::Topic(Topic::ChangePopulation),
             Channel::Topic(Topic::PopulationReport),
             Channel::TimelineEvent, // Wraps `ChangePersonStatus`
 
@@ -208,7 +434,7 @@ impl Actor<Message, Topic> for Population {
 
         ];
 
-        (subscriptions, vec![initial_population_report])
+        Ok((subscriptions, initial_population_reports))
     }
 }
 
@@ -216,14 +442,28 @@ impl Actor<Message, Topic> for Population {
 
 #[cfg(test)]
 mod test {
-    use std::assert_matches::assert_matches;
-    use crate::{RcActor, Router};
+    use actor_model::{rc_cell, router::Router as GenericRouter};
+    use crate::Router;
     use super::*;
 
+    #[test]
+    fn test_infection_status_codes() {
+        let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        writer.serialize((InfectionStatus::Susceptible, InfectionStatus::Infected, InfectionStatus::Recovered)).unwrap();
+        let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(written, "0,1,2\n");
+
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(written.as_bytes());
+        let statuses: (InfectionStatus, InfectionStatus, InfectionStatus) = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(statuses, (InfectionStatus::Susceptible, InfectionStatus::Infected, InfectionStatus::Recovered));
+
+        assert!(InfectionStatus::try_from(3).is_err());
+    }
+
     #[test]
     fn test_person_creation() {
         let mut context = Router::new();
-        context.add_actor(RcActor::new(Population::new(10)));
+        context.add_actor(rc_cell!(Population::new(10))).unwrap();
 
         // Let's request the status of person_id 0.
         let response = context.silent_route(Message::make_person_status_request(0, 0));
@@ -231,31 +471,31 @@ mod test {
         // There should be exactly 1 response.
         assert_eq!(1, response.len());
 
-        assert_matches!(
-            response[0],
+        assert!(matches!(
+            *response[0],
             Envelope{
                 message: Some(
-                    Message::PersonStatus(0, InfectionStatus::Susceptible)
+                    Message::PersonStatus(0, 0, InfectionStatus::Susceptible)
                     ),
                 ..
             }
-        );
+        ));
 
         // Now set the status of person_id 0 to infected
         let response = context.silent_route(Message::make_person_status_change(0, 0, InfectionStatus::Infected, 1.0.into()));
 
-        // There should be exactly 1 response, a population report.
+        // There should be exactly 1 response, the person's new status.
         assert_eq!(1, response.len());
 
-        assert_matches!(
-            response[0],
+        assert!(matches!(
+            *response[0],
             Envelope{
                 message: Some(
-                    Message::PopulationReport{susceptible: 9, infected: 1, recovered: 0}
+                    Message::PersonStatus(0, 0, InfectionStatus::Infected)
                     ),
                 ..
             }
-        );
+        ));
 
         // Request the status of person_id 0 again.
         let response = context.silent_route(Message::make_person_status_request(0, 0));
@@ -263,36 +503,170 @@ mod test {
         // There should be exactly 1 response.
         assert_eq!(1, response.len());
 
-        assert_matches!(
-            response[0],
+        assert!(matches!(
+            *response[0],
             Envelope{
                 message: Some(
-                    Message::PersonStatus(0, InfectionStatus::Infected)
+                    Message::PersonStatus(0, 0, InfectionStatus::Infected)
                     ),
                 ..
             }
+        ));
+
+    }
+
+    #[test]
+    fn test_no_op_status_change_is_silent() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new(2))).unwrap();
+
+        context.silent_route(Message::make_person_status_change(0, 1, InfectionStatus::Infected, 1.0.into()));
+        let response = context.silent_route(Message::make_person_status_change(0, 1, InfectionStatus::Infected, 2.0.into()));
+
+        // Person 1 was already infected, so nobody hears about it again.
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn test_ids_by_status() {
+        let mut population: Population = Population::new(5);
+        population.set_person_status(1, InfectionStatus::Infected);
+        population.set_person_status(3, InfectionStatus::Infected);
+        population.set_person_status(4, InfectionStatus::Recovered);
+        population.remove_person(0);
+
+        assert_eq!(population.susceptible_ids().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(population.infected_ids().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(population.recovered_ids().collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn test_all_recovered() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new(1))).unwrap();
+
+        context.silent_route(Message::make_person_status_change(0, 0, InfectionStatus::Infected, 1.0.into()));
+        let response = context.silent_route(Message::make_person_status_change(0, 0, InfectionStatus::Recovered, 2.0.into()));
+
+        // The new status followed by the announcement that everyone has recovered.
+        assert_eq!(2, response.len());
+        assert_eq!(response[1].channel, Channel::Topic(Topic::AllRecovered));
+    }
+
+    #[test]
+    fn test_age_request() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new_with_ages(3, vec![4, 40, 80]))).unwrap();
+
+        let response = context.silent_route(Message::make_age_request(0, 1));
+
+        assert_eq!(1, response.len());
+        assert_eq!(response[0].channel, Channel::Topic(Topic::Age));
+        assert_eq!(response[0].message, Some(Message::AgeResponse(1, 40)));
+    }
+
+    #[test]
+    fn test_add_and_remove_people() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new(2))).unwrap();
+
+        let response = context.silent_route(Message::make_add_person(0));
+        assert_eq!(2, response.len());
+        assert_eq!(response[0].message, Some(Message::PersonAdded(2)));
+        assert_eq!(
+            response[1].message,
+            Some(Message::PopulationReport{ susceptible: 3, infected: 0, recovered: 0 })
+        );
+
+        let response = context.silent_route(Message::make_remove_person(0, 0));
+        assert_eq!(2, response.len());
+        assert_eq!(response[0].message, Some(Message::PersonRemoved(0)));
+        assert_eq!(
+            response[1].message,
+            Some(Message::PopulationReport{ susceptible: 2, infected: 0, recovered: 0 })
         );
 
+        // A removed person has no status and can't be removed again.
+        assert!(context.silent_route(Message::make_person_status_request(0, 0)).is_empty());
+        assert!(context.silent_route(Message::make_remove_person(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_removed_ids_are_not_reused() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new(2))).unwrap();
+
+        // Infect the last person and schedule their recovery, then remove them before it happens.
+        context.silent_route(Message::make_person_status_change(0, 1, InfectionStatus::Infected, 1.0.into()));
+        let recovery = Message::PersonStatus(1, 0, InfectionStatus::Recovered);
+        context.silent_route(RcEnvelope::new(Envelope::new(0, Channel::ScheduleEvent, Some(recovery), Some(2.0.into()))));
+        context.silent_route(Message::make_remove_person(0, 1));
+
+        let response = context.silent_route(Message::make_add_person(0));
+        assert_eq!(response[0].message, Some(Message::PersonAdded(2)));
+
+        // The recovery finds nobody to recover, and the new person doesn't inherit it.
+        context.run();
+        let response = context.silent_route(Message::make_person_status_request(0, 2));
+        assert_eq!(response[0].message, Some(Message::PersonStatus(2, 0, InfectionStatus::Susceptible)));
+        assert!(context.silent_route(Message::make_person_status_request(0, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_sub_populations() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new_with_subpopulations(vec![2, 3]))).unwrap();
+
+        let response = context.silent_route(Message::make_person_status_request(0, 3));
+        assert_eq!(response[0].message, Some(Message::PersonStatus(3, 1, InfectionStatus::Susceptible)));
+
+        // The status change is announced with the person's sub-population.
+        let response = context.silent_route(Message::make_person_status_change(0, 4, InfectionStatus::Infected, 1.0.into()));
+        assert_eq!(response[0].message, Some(Message::PersonStatus(4, 1, InfectionStatus::Infected)));
+
+        let response = context.silent_route(Message::make_population_report_request(0));
+        let reports: Vec<_> = response.iter().map(|envelope| envelope.message).collect();
+        assert_eq!(reports, vec![
+            Some(Message::PopulationReport{ susceptible: 4, infected: 1, recovered: 0 }),
+            Some(Message::SubPopulationReport{ sub_population: 0, susceptible: 2, infected: 0, recovered: 0 }),
+            Some(Message::SubPopulationReport{ sub_population: 1, susceptible: 2, infected: 1, recovered: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn test_u64_person_ids() {
+        let mut context = GenericRouter::<Message<u64>, Topic>::new();
+        context.add_actor(rc_cell!(Population::<u64>::new(3))).unwrap();
+
+        let request = |channel, message| {
+            RcEnvelope::<u64>::new(Envelope::new(0, Channel::Topic(channel), Some(message), None))
+        };
+
+        let response = context.silent_route(request(Topic::RequestPersonStatus, Message::RequestPersonStatus(2)));
+        assert_eq!(response[0].message, Some(Message::PersonStatus(2u64, 0, InfectionStatus::Susceptible)));
+
+        let response = context.silent_route(request(Topic::ChangePopulation, Message::AddPerson));
+        assert_eq!(response[0].message, Some(Message::PersonAdded(3u64)));
     }
 
     #[test]
     fn test_get_population() {
         let mut context = Router::new();
-        context.add_actor(RcActor::new(Population::new(10)));
+        context.add_actor(rc_cell!(Population::new(10))).unwrap();
 
         let response = context.silent_route(Message::make_population_report_request(0));
 
         // There should be exactly 1 response, a population report.
         assert_eq!(1, response.len());
 
-        assert_matches!(
-            response[0],
+        assert!(matches!(
+            *response[0],
             Envelope{
                 message: Some(
                     Message::PopulationReport{susceptible: 10, infected: 0, recovered: 0}
                     ),
                 ..
             }
-        );
+        ));
     }
 }
@@ -0,0 +1,71 @@
+/*!
+
+A `TerminatorActor` ends the simulation once everyone has recovered. Keeping this decision in
+its own actor means `Population` only reports what happened and doesn't need to know
+anything about the simulation's lifecycle.
+
+*/
+
+use actor_model::actor::{Actor, ActorHandle};
+
+use crate::message::{ActorResult, Registration, Channel, Envelope, Message, Topic};
+
+pub struct TerminatorActor {
+    handle: ActorHandle,
+}
+
+impl TerminatorActor {
+    pub fn new() -> Self {
+        TerminatorActor {
+            handle: 0,
+        }
+    }
+}
+
+impl Actor<Message, Topic> for TerminatorActor {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+        let messages = match *envelope {
+            Envelope {
+                channel: Channel::Topic(Topic::AllRecovered),
+                ..
+            } => {
+                vec![Message::make_stop_message(self.handle)]
+            }
+
+            _ => {
+                vec![]
+            }
+        };
+
+        #[cfg(feature = "print_messages")]
+        for message in &messages {
+            println!("TERMINATOR: {:?}", message);
+        }
+
+        Ok(messages)
+    }
+
+    fn register(&mut self, handle: ActorHandle) -> Registration {
+        self.handle = handle;
+
+        Ok((vec![Channel::Topic(Topic::AllRecovered)], vec![]))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use actor_model::{harness::ActorTestHarness, rc_cell};
+    use super::*;
+
+    #[test]
+    fn test_stops_when_all_recovered() {
+        let mut harness = ActorTestHarness::new(rc_cell!(TerminatorActor::new()));
+        assert!(harness.is_subscribed(&Channel::Topic(Topic::AllRecovered)));
+
+        let response = harness.send(Message::make_all_recovered(0)).unwrap();
+
+        assert_eq!(1, response.len());
+        assert_eq!(response[0].channel, Channel::Stop);
+    }
+}
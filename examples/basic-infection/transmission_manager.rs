@@ -20,22 +20,27 @@ the beginning of the simulation and schedules their infection at that time.
 
 */
 
+use std::borrow::Cow;
+
 use rand::{prelude::SmallRng, SeedableRng};
 use rand_distr::{Distribution, Exp, Uniform};
 
 use actor_model::{
-    actor::{Actor, ActorHandle},
+    actor::{Actor, ActorError, ActorHandle},
     timeline::Time,
 };
 
 use crate::{
     message::{
+        ActorResult,
+        Registration,
         Channel,
         Envelope,
         Message,
         RcEnvelope,
         Topic
     },
+    infection_model::BoxedInfectionModel,
     people::{InfectionStatus, PersonID},
     FOI,
     MAX_TIME,
@@ -45,9 +50,20 @@ pub struct TransmissionManager {
     handle              : ActorHandle,
     rng                 : SmallRng,
     exp_dist            : Exp<f64>,
-    people_count        : u32,
+    /// The people attempts choose from. The population starts out as people `0..n`, where `n`
+    /// is the size in the first population report, and changes as people are added and removed.
+    person_ids          : Vec<PersonID>,
+    /// Whether the first attempt has been made. Later population reports don't start another
+    /// chain of attempts.
+    started             : bool,
+    /// Whether the chain of attempts is waiting for someone to be added to an empty population.
+    stalled             : bool,
     current_attempt_time: Time,
     selected_person     : Option<PersonID>,
+    /// The number of infection attempts left, or `None` if attempts are unlimited.
+    remaining_attempts  : Option<u32>,
+    /// If set, decides the time between attempts instead of `exp_dist`.
+    infection_model     : Option<BoxedInfectionModel>,
 }
 
 impl TransmissionManager {
@@ -56,9 +72,37 @@ impl TransmissionManager {
             handle              : 0,
             rng                 : SmallRng::seed_from_u64(42),
             exp_dist            : Exp::new(FOI).unwrap(),
-            people_count        : 0,
+            person_ids          : vec![],
+            started             : false,
+            stalled             : false,
             current_attempt_time: 0.0.into(),
             selected_person     : None,
+            remaining_attempts  : None,
+            infection_model     : None,
+        }
+    }
+
+    /// Caps the total number of infection attempts. Once `max_attempts` attempts have been
+    /// made no further attempts are scheduled, even if there is time left.
+    #[allow(dead_code)]
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.remaining_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Times infection attempts with `infection_model` instead of the constant `FOI`. A
+    /// `Message::UpdateFOI` then has no effect but to raise a warning.
+    #[allow(dead_code)]
+    pub fn with_infection_model(mut self, infection_model: BoxedInfectionModel) -> Self {
+        self.infection_model = Some(infection_model);
+        self
+    }
+
+    /// Samples the time from the current attempt to the next, for a population of one.
+    fn next_attempt_delta(&mut self) -> f64 {
+        match &self.infection_model {
+            Some(infection_model) => infection_model.next_attempt_delta(self.current_attempt_time, &mut self.rng),
+            None                  => self.exp_dist.sample(&mut self.rng),
         }
     }
 
@@ -67,10 +111,14 @@ impl TransmissionManager {
         let mut messages = vec![];
 
         if let Some(person_id) = self.selected_person {
-            // Schedule the next attempt if there is time left
+            if let Some(remaining_attempts) = self.remaining_attempts.as_mut() {
+                *remaining_attempts = remaining_attempts.saturating_sub(1);
+            }
+
+            // Schedule the next attempt if there is time left and we haven't run out of attempts
             let next_attempt_time =
-                self.current_attempt_time + self.exp_dist.sample(&mut self.rng) / (self.people_count as f64);
-            if next_attempt_time <= MAX_TIME {
+                self.current_attempt_time + self.next_attempt_delta() / (self.person_ids.len() as f64);
+            if next_attempt_time <= MAX_TIME && self.remaining_attempts != Some(0) {
                 messages.push(Message::make_schedule_attempt_infection(self.handle, next_attempt_time))
             }
 
@@ -93,20 +141,49 @@ impl TransmissionManager {
         messages
     }
 
+    /// Starts an infection attempt by querying a random person or, if there is nobody to
+    /// infect, stalls the chain of attempts until someone is added.
+    fn start_attempt(&mut self) -> Vec<RcEnvelope> {
+        if self.person_ids.is_empty() {
+            self.stalled = true;
+            return vec![];
+        }
+
+        vec![self.query_random_person_id()]
+    }
+
     /// Uniformly selects a person from the population to attempt to infect and requests
-    /// their infection status.
+    /// their infection status. The population must not be empty.
     fn query_random_person_id(&mut self) -> RcEnvelope {
-        let person_id: PersonID = Uniform::new_inclusive(0, self.people_count - 1)
+        let index: u32 = Uniform::new_inclusive(0, self.person_ids.len() as u32 - 1)
             .unwrap()
             .sample(&mut self.rng);
+        let person_id = self.person_ids[index as usize];
 
         self.selected_person = Some(person_id);
         Message::make_person_status_request(self.handle, person_id)
     }
+
+    /// Forgets the removed person. If they were about to be infected, the `Population` won't
+    /// answer for them, so the attempt moves on without them.
+    fn remove_person(&mut self, person_id: PersonID) -> Vec<RcEnvelope> {
+        self.person_ids.retain(|&id| id != person_id);
+        if self.selected_person != Some(person_id) {
+            return vec![];
+        }
+
+        if self.person_ids.is_empty() {
+            self.selected_person = None;
+            self.stalled         = true;
+            return vec![];
+        }
+        // Nobody is infected, but the next attempt is scheduled.
+        self.attempt_infection(InfectionStatus::Recovered)
+    }
 }
 
 impl Actor<Message, Topic> for TransmissionManager {
-    fn receive_message(&mut self, envelope: RcEnvelope) -> Vec<RcEnvelope> {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
         // In general, we have a method that responds to every message type we know how to answer.
 
         let messages = match *envelope {
@@ -121,24 +198,42 @@ impl Actor<Message, Topic> for TransmissionManager {
                      }),
                 ..
             } => {
-                let mut messages = vec![];
-                // Here we can use `self.people_count` as a signal that we should schedule
-                // the first infection attempt.
-                if self.people_count == 0 {
-                    self.people_count = susceptible + infected + recovered;
-                    // Initiate first infection attempt.
-                    messages.push(self.query_random_person_id());
+                // Only the first report matters. After that, we follow the population through
+                // `PersonAdded` and `PersonRemoved`.
+                if self.started {
+                    return Ok(vec![]);
+                }
+                self.started    = true;
+                self.person_ids = (0..susceptible + infected + recovered).collect();
+                // Initiate first infection attempt.
+                self.start_attempt()
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::PopulationChanged),
+                message: Some(Message::PersonAdded(person_id)),
+                ..
+            } => {
+                self.person_ids.push(person_id);
+                if self.stalled {
+                    self.stalled = false;
+                    self.start_attempt()
                 } else {
-                    // In this model the size of the population is constant, but in
-                    // other models it may change.
-                    self.people_count = susceptible + infected + recovered;
+                    vec![]
                 }
-                messages
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::PopulationChanged),
+                message: Some(Message::PersonRemoved(person_id)),
+                ..
+            } => {
+                self.remove_person(person_id)
             }
 
             Envelope {
                 channel: Channel::Topic(Topic::PersonStatus),
-                message: Some(Message::PersonStatus(person_id, infection_status)),
+                message: Some(Message::PersonStatus(person_id, _, infection_status)),
                 ..
             } => {
                 if Some(person_id) == self.selected_person {
@@ -154,7 +249,31 @@ impl Actor<Message, Topic> for TransmissionManager {
                 ..
             } => {
                 // It's time for Typhoid Mary to make the donuts.
-                vec![self.query_random_person_id()]
+                self.start_attempt()
+            }
+
+            Envelope {
+                channel: Channel::Topic(Topic::UpdateFOI),
+                message: Some(Message::UpdateFOI(foi)),
+                ..
+            } => {
+                if self.infection_model.is_some() {
+                    let warning = Cow::Borrowed("force of infection not updated: an infection model times the attempts");
+                    return Ok(vec![RcEnvelope::new(Envelope::new(self.handle, Channel::Warning(warning), None, None))]);
+                }
+                // The attempt already on the timeline keeps its time; the new force of
+                // infection takes effect from the next one.
+                self.exp_dist = Exp::new(foi.0)
+                    .map_err(|error| ActorError::Recoverable(format!("invalid force of infection {}: {}", foi, error)))?;
+                vec![]
+            }
+
+            Envelope {
+                channel: Channel::Time,
+                message: Some(Message::NextAttemptTimeRequest),
+                ..
+            } => {
+                vec![Message::make_next_attempt_time_response(self.handle, self.current_attempt_time)]
             }
 
             _ => {
@@ -168,20 +287,23 @@ impl Actor<Message, Topic> for TransmissionManager {
             println!("TRANSMISSION MANAGER: {:?}", message);
         }
 
-        messages
+        Ok(messages)
     }
 
-    fn register(&mut self, handle: ActorHandle) -> (Vec<Channel>, Vec<RcEnvelope>) {
+    fn register(&mut self, handle: ActorHandle) -> Registration {
         self.handle = handle;
 
         let subscriptions = vec![
             Channel::Topic(Topic::PopulationReport),
+            Channel::Topic(Topic::PopulationChanged),
             Channel::Topic(Topic::PersonStatus),
+            Channel::Topic(Topic::UpdateFOI),
             Channel::TimelineEvent,
+            Channel::Time,
         ];
 
         // We have no messages to send until we know the population size.
-        (subscriptions, vec![])
+        Ok((subscriptions, vec![]))
     }
 }
 
@@ -209,3 +331,134 @@ mod test {
   }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use crate::infection_model::SeasonalFOI;
+    use super::*;
+
+    fn schedules_next_attempt(messages: &[RcEnvelope]) -> bool {
+        messages.iter().any(|envelope| envelope.channel == Channel::ScheduleEvent)
+    }
+
+    /// The person the messages ask about, if they include a status request.
+    fn queried_person(messages: &[RcEnvelope]) -> Option<PersonID> {
+        messages.iter().find_map(|envelope| match envelope.message {
+            Some(Message::RequestPersonStatus(person_id)) => Some(person_id),
+            _                                             => None,
+        })
+    }
+
+    #[test]
+    fn test_next_attempt_time_query() {
+        let mut manager = TransmissionManager::new();
+        manager.person_ids = (0..10).collect();
+        manager.selected_person = Some(0);
+        manager.attempt_infection(InfectionStatus::Recovered);
+        let expected = manager.current_attempt_time;
+
+        let response = manager.receive_message(&Message::make_next_attempt_time_request(1)).unwrap();
+        assert_eq!(response.len(), 1);
+        assert_eq!(response[0].message, Some(Message::NextAttemptTimeResponse(expected)));
+    }
+
+    #[test]
+    fn test_update_foi() {
+        let mut manager = TransmissionManager::new();
+        let response = manager.receive_message(&Message::make_update_foi(1, FOI / 2.0)).unwrap();
+
+        assert!(response.is_empty());
+        assert_eq!(manager.exp_dist, Exp::new(FOI / 2.0).unwrap());
+
+        // An invalid force of infection is refused, and the old one kept.
+        for foi in [-1.0, f64::NAN] {
+            let result = manager.receive_message(&Message::make_update_foi(1, foi));
+            assert!(matches!(result, Err(ActorError::Recoverable(_))));
+        }
+        assert_eq!(manager.exp_dist, Exp::new(FOI / 2.0).unwrap());
+    }
+
+    #[test]
+    fn test_update_foi_with_infection_model_warns() {
+        let mut manager = TransmissionManager::new()
+            .with_infection_model(Box::new(SeasonalFOI::new(FOI, 0.5, 100.0)));
+        let response = manager.receive_message(&Message::make_update_foi(1, FOI / 2.0)).unwrap();
+
+        assert_eq!(response.len(), 1);
+        assert!(matches!(response[0].channel, Channel::Warning(_)));
+        assert_eq!(manager.exp_dist, Exp::new(FOI).unwrap());
+    }
+
+    #[test]
+    fn test_infection_model_times_attempts() {
+        // With the same seed, a season at its mean matches the constant force of infection.
+        let mut constant = TransmissionManager::new();
+        let mut seasonal = TransmissionManager::new()
+            .with_infection_model(Box::new(SeasonalFOI::new(FOI, 0.5, 100.0)));
+        for manager in [&mut constant, &mut seasonal] {
+            manager.person_ids      = (0..10).collect();
+            manager.selected_person = Some(0);
+            manager.attempt_infection(InfectionStatus::Recovered);
+        }
+
+        assert!((constant.current_attempt_time.0 - seasonal.current_attempt_time.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_attempts_choose_from_the_live_population() {
+        let mut manager = TransmissionManager::new();
+        manager.receive_message(&Message::make_population_report(0, 3, 0, 0)).unwrap();
+        manager.receive_message(&Message::make_person_removed(0, 1)).unwrap();
+        manager.receive_message(&Message::make_person_added(0, 3)).unwrap();
+
+        let mut queried = vec![];
+        for _ in 0..50 {
+            manager.selected_person = None;
+            queried.extend(queried_person(&manager.start_attempt()));
+        }
+        assert!(queried.iter().all(|person_id| [0, 2, 3].contains(person_id)));
+        assert!(queried.contains(&3));
+    }
+
+    #[test]
+    fn test_removing_the_queried_person_moves_the_attempt_on() {
+        let mut manager = TransmissionManager::new();
+        let messages    = manager.receive_message(&Message::make_population_report(0, 2, 0, 0)).unwrap();
+        let person_id   = queried_person(&messages).unwrap();
+
+        // The `Population` won't answer for a removed person, so the next attempt is scheduled now.
+        let messages = manager.receive_message(&Message::make_person_removed(0, person_id)).unwrap();
+        assert!(schedules_next_attempt(&messages));
+        assert_eq!(manager.selected_person, None);
+    }
+
+    #[test]
+    fn test_emptied_population_resumes_a_single_chain() {
+        let mut manager = TransmissionManager::new();
+        let messages    = manager.receive_message(&Message::make_population_report(0, 1, 0, 0)).unwrap();
+        assert_eq!(queried_person(&messages), Some(0));
+
+        // The only person is removed before they can be infected, and the population is empty.
+        assert!(manager.receive_message(&Message::make_person_removed(0, 0)).unwrap().is_empty());
+        assert!(manager.receive_message(&Message::make_population_report(0, 0, 0, 0)).unwrap().is_empty());
+
+        // Someone new resumes the attempts, and the report that follows doesn't start more.
+        let messages = manager.receive_message(&Message::make_person_added(0, 1)).unwrap();
+        assert_eq!(queried_person(&messages), Some(1));
+        assert!(manager.receive_message(&Message::make_population_report(0, 1, 0, 0)).unwrap().is_empty());
+        assert!(manager.receive_message(&Message::make_person_added(0, 2)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let mut manager = TransmissionManager::new().with_max_attempts(2);
+        manager.person_ids = (0..10).collect();
+
+        manager.selected_person = Some(0);
+        assert!(schedules_next_attempt(&manager.attempt_infection(InfectionStatus::Recovered)));
+
+        // The second attempt is the last one, so nothing further is scheduled.
+        manager.selected_person = Some(0);
+        assert!(!schedules_next_attempt(&manager.attempt_infection(InfectionStatus::Recovered)));
+    }
+}
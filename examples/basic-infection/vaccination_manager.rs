@@ -0,0 +1,100 @@
+/*!
+
+A `VaccinationManager` immunizes a random fraction of the population when the simulation
+starts. Vaccinated people go straight from susceptible to recovered, representing prior
+immunity, so they can never be infected.
+
+*/
+
+use rand::{prelude::SmallRng, seq::index, SeedableRng};
+
+use actor_model::actor::{Actor, ActorHandle};
+
+use crate::{
+    message::{ActorResult, Registration, Channel, Envelope, Message, Topic},
+    people::{InfectionStatus, PersonID},
+};
+
+pub struct VaccinationManager {
+    handle         : ActorHandle,
+    rng            : SmallRng,
+    population_size: u32,
+    /// The fraction of the population to vaccinate, between 0 and 1.
+    coverage       : f64,
+}
+
+impl VaccinationManager {
+    pub fn new(population_size: u32, coverage: f64) -> Self {
+        assert!((0.0..=1.0).contains(&coverage), "coverage must be between 0 and 1");
+
+        VaccinationManager {
+            handle: 0,
+            rng   : SmallRng::seed_from_u64(42),
+            population_size,
+            coverage,
+        }
+    }
+}
+
+impl Actor<Message, Topic> for VaccinationManager {
+    fn receive_message(&mut self, envelope: &Envelope) -> ActorResult {
+        let messages = match *envelope {
+            Envelope {
+                channel: Channel::SimulationStarted,
+                time,
+                ..
+            } => {
+                let vaccinated_count = (self.coverage * self.population_size as f64).round() as usize;
+                // Sample without replacement so that nobody is vaccinated twice.
+                index::sample(&mut self.rng, self.population_size as usize, vaccinated_count)
+                    .into_iter()
+                    .map(|person_id| Message::make_person_status_change(
+                        self.handle,
+                        person_id as PersonID,
+                        InfectionStatus::Recovered,
+                        time,
+                    ))
+                    .collect()
+            }
+
+            _ => {
+                vec![]
+            }
+        };
+
+        #[cfg(feature = "print_messages")]
+        for message in &messages {
+            println!("VACCINATION MANAGER: {:?}", message);
+        }
+
+        Ok(messages)
+    }
+
+    fn register(&mut self, handle: ActorHandle) -> Registration {
+        self.handle = handle;
+
+        Ok((vec![Channel::SimulationStarted], vec![]))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use actor_model::rc_cell;
+    use crate::{people::Population, Router};
+    use super::*;
+
+    #[test]
+    fn test_vaccination_coverage() {
+        let mut context = Router::new();
+        context.add_actor(rc_cell!(Population::new(100))).unwrap();
+        context.add_actor(rc_cell!(VaccinationManager::new(100, 0.3))).unwrap();
+        context.run();
+
+        let response = context.silent_route(Message::make_population_report_request(0));
+        assert_eq!(
+            response[0].message,
+            Some(Message::PopulationReport { susceptible: 70, infected: 0, recovered: 30 })
+        );
+    }
+}
@@ -4,29 +4,177 @@ An `Actor` is an entity that can emit messages and subscribe to channels.
 
 */
 
-use std::fmt::Debug;
+use std::{
+  any::Any,
+  fmt::{self, Debug, Display}
+};
 
 use crate::{
   message::{
     Channel,
+    Envelope,
     RcEnvelope,
     BoundedTopic
   },
-  rccell::RcCell,
+  rccell::{RcCell, Shared},
+  router::SetupContext,
 };
 
 pub type ActorHandle = u32;
+#[cfg(not(feature = "send"))]
 pub type RcActor<Message, Topic> = RcCell<dyn Actor<Message, Topic>>;
+/// With the `send` feature, actors must be `Send` so that the `Router` that owns them is.
+#[cfg(feature = "send")]
+pub type RcActor<Message, Topic> = RcCell<dyn Actor<Message, Topic> + Send>;
+/// An actor's cell as `dyn Any`, from which `Router::share()` recovers the actor's concrete
+/// type.
+#[cfg(not(feature = "send"))]
+pub type AnyActor = Shared<dyn Any>;
+#[cfg(feature = "send")]
+pub type AnyActor = Shared<dyn Any + Send + Sync>;
+/// `Send` with the `send` feature, and no requirement otherwise.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "send"))]
+impl<T: ?Sized> MaybeSend for T {}
+#[cfg(feature = "send")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "send")]
+impl<T: ?Sized + Send> MaybeSend for T {}
+/// What an `Actor` returns from `receive_message()`: its responses, or the reason it failed.
+pub type ActorResult<Message, Topic> = Result<Vec<RcEnvelope<Message, Topic>>, ActorError>;
+
+/// Returned by an `Actor` that could not handle a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ActorError {
+  /// The actor cannot continue. The `Router` calls `Actor::on_error()` and stops delivering
+  /// messages to the actor.
+  Fatal(String),
+  /// The actor could not handle this message but can handle others. The `Router` raises a
+  /// warning and carries on.
+  Recoverable(String),
+}
+
+impl Display for ActorError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ActorError::Fatal(reason)       => write!(f, "fatal error: {}", reason),
+      ActorError::Recoverable(reason) => write!(f, "recoverable error: {}", reason),
+    }
+  }
+}
+
+impl std::error::Error for ActorError {}
+
+/// Returned by an `Actor` that refuses to be added to a `Router`, e.g. because an actor it
+/// depends on has not been added yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistrationError(pub String);
+
+impl Display for RegistrationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "registration rejected: {}", self.0)
+  }
+}
+
+impl std::error::Error for RegistrationError {}
+
+/// What an `Actor` returns from `register()`: its subscriptions and initial messages, or the
+/// reason it refuses to be added.
+pub type Registration<Message, Topic> = Result<(Vec<Channel<Topic>>, Vec<RcEnvelope<Message, Topic>>), RegistrationError>;
 
 pub trait Actor<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
 {
-  /// A message is delivered to this `Actor`. The `Actor` has the opportunity to respond
-  fn receive_message(&mut self, envelope: RcEnvelope<Message, Topic>) -> Vec<RcEnvelope<Message, Topic>>;
+  /// A message is delivered to this `Actor`. The `Actor` has the opportunity to respond, or
+  /// to report that it failed. (Implementations written before `ActorError` existed only need
+  /// to wrap their responses in `Ok`.) The `Router` keeps the envelope's reference count, so
+  /// the actor only borrows the envelope.
+  fn receive_message(&mut self, envelope: &Envelope<Message, Topic>) -> ActorResult<Message, Topic>;
+
+  /// Called by the `Router` before each message is delivered to this `Actor`. Returning
+  /// `false` turns the message away: `receive_message()` is not called, and the message does
+  /// not count as delivered to this `Actor`. Accepts everything by default.
+  fn pre_receive(&mut self, _envelope: &Envelope<Message, Topic>) -> bool {
+    true
+  }
 
   /// Called when the `Router` is adding this actor with the provided `ActorHandle`.
   /// Implementations should store their own `ActorHandle` for later use. The
-  /// `Actor` has an opportunity to subscribe to channels and send initial messages.
-  fn register(&mut self, handle: ActorHandle) -> (Vec<Channel<Topic>>, Vec<RcEnvelope<Message, Topic>>);
+  /// `Actor` has an opportunity to subscribe to channels and send initial messages, or to
+  /// return a `RegistrationError` to refuse to be added.
+  fn register(&mut self, handle: ActorHandle) -> Registration<Message, Topic>;
+
+  /// Called once, after the actors have been added and before `Router::run()` starts the
+  /// simulation, so the `Actor` can configure itself with the `Router` at hand, e.g. to
+  /// subscribe to more channels or to look at the other actors (see `SetupContext`). An actor
+  /// added between runs is set up at the start of the next run.
+  fn setup(&mut self, _router: &mut dyn SetupContext<Message, Topic>) {}
+
+  /// A human-readable name for this `Actor`, used in diagnostics. Defaults to the type name.
+  /// Dotted names, e.g. `"disease.infection_manager"`, put actors in groups (see
+  /// `Router::actors_in_group()`).
+  fn name(&self) -> &str {
+    std::any::type_name::<Self>()
+  }
+
+  /// A human-readable dump of this `Actor`'s state, used by the debugger's `inspect`
+  /// command. Actors that don't provide one return `None`.
+  fn snapshot(&self) -> Option<String> {
+    None
+  }
+
+  /// Returns a deep copy of this `Actor`, if it supports cloning. Used by
+  /// `Router::clone_for_testing()` to snapshot a simulation. Implementations typically
+  /// return `rc_cell!(self.clone())`.
+  fn clone_actor(&self) -> Option<RcActor<Message, Topic>> {
+    None
+  }
+
+  /// The version of this `Actor`'s code. `Router::replace_actor()` only replaces an actor
+  /// with one of a higher version.
+  fn version(&self) -> u32 {
+    0
+  }
+
+  /// Called when `receive_message()` returns `ActorError::Fatal`, just before the `Router`
+  /// stops delivering messages to this `Actor`. A chance to, e.g., flush output.
+  fn on_error(&mut self, _error: &ActorError) {}
+
+  /// Called when `Router::remove_actor()` removes this `Actor`, just before the remaining
+  /// actors are sent a `Channel::Tombstone`. A chance to, e.g., flush output.
+  fn on_stop(&mut self) {}
+}
+
+/// What `Router::add_actor()` accepts: an `RcActor`, or an `RcCell` of a concrete actor type.
+/// Only an actor added as the latter can be recovered with `Router::share()` or
+/// `Router::typed_actor_by_name()`, because an `RcActor` has forgotten its type.
+pub trait IntoRcActor<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  /// The actor, and the same actor as `Any` if its type is known.
+  fn into_rc_actor(self) -> (RcActor<Message, Topic>, Option<AnyActor>);
+}
+
+impl<Message, Topic> IntoRcActor<Message, Topic> for RcActor<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  fn into_rc_actor(self) -> (RcActor<Message, Topic>, Option<AnyActor>) {
+    (self, None)
+  }
+}
+
+impl<Message, Topic, A> IntoRcActor<Message, Topic> for RcCell<A>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          A      : Actor<Message, Topic> + MaybeSend + 'static
+{
+  fn into_rc_actor(self) -> (RcActor<Message, Topic>, Option<AnyActor>) {
+    (RcCell(self.0.clone()), Some(self.0))
+  }
 }
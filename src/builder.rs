@@ -0,0 +1,234 @@
+/*!
+
+A `SimulationBuilder` builds a `Router` from a TOML file that lists the actors to add, so a
+simulation can be reconfigured without recompiling. Each actor type that may appear in the file
+is registered with a factory that constructs the actor from its parameters:
+
+```rust
+# use actor_model::{
+#   actor::{Actor, ActorHandle, ActorResult, Registration},
+#   builder::SimulationBuilder,
+#   message::Envelope,
+#   rc_cell
+# };
+struct Greeter {
+  greeting: String,
+}
+
+impl Actor<u32, ()> for Greeter {
+  fn receive_message(&mut self, _envelope: &Envelope<u32, ()>) -> ActorResult<u32, ()> {
+    Ok(vec![])
+  }
+
+  fn register(&mut self, _handle: ActorHandle) -> Registration<u32, ()> {
+    Ok((vec![], vec![]))
+  }
+}
+
+let builder = SimulationBuilder::<u32, ()>::new()
+  .register_factory("Greeter", |parameters| {
+    let greeting = parameters.get("greeting").and_then(|value| value.as_str()).ok_or("missing greeting")?;
+    Ok(rc_cell!(Greeter { greeting: greeting.to_string() }))
+  });
+
+let router = builder.from_toml_str(r#"
+  [[actor]]
+  type     = "Greeter"
+  greeting = "hello"
+"#).unwrap();
+assert_eq!(router.actors().len(), 1);
+```
+
+Actors are added in the order they appear in the file, so the `n`th `[[actor]]` gets handle
+`n - 1`. The `type` key chooses the factory; the factory receives the whole table, including
+`type`.
+
+*/
+
+use std::{
+  collections::HashMap,
+  fmt::{self, Debug, Display},
+  io,
+  path::Path
+};
+
+use toml::Table;
+
+use crate::{
+  actor::{RcActor, RegistrationError},
+  message::BoundedTopic,
+  router::Router
+};
+
+/// Constructs an actor from its table in the configuration file, or says what is wrong with it.
+pub type ActorFactory<Message, Topic> = Box<dyn Fn(&Table) -> Result<RcActor<Message, Topic>, String>>;
+
+/// Why a `SimulationBuilder` could not build a `Router`.
+#[derive(Debug)]
+pub enum BuilderError {
+  /// The configuration file could not be read.
+  Io(io::Error),
+  /// The configuration is not valid TOML.
+  Parse(toml::de::Error),
+  /// The configuration is valid TOML, but not a list of actors.
+  Malformed(String),
+  /// No factory is registered for the actor type.
+  UnknownActorType(String),
+  /// The factory for `actor_type` rejected its parameters.
+  Factory {
+    actor_type: String,
+    reason    : String,
+  },
+  /// The `index`th actor, of type `actor_type`, refused registration.
+  Registration {
+    index     : usize,
+    actor_type: String,
+    error     : RegistrationError,
+  },
+}
+
+impl Display for BuilderError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      BuilderError::Io(error)                      => write!(f, "could not read configuration: {}", error),
+      BuilderError::Parse(error)                   => write!(f, "could not parse configuration: {}", error),
+      BuilderError::Malformed(reason)              => write!(f, "malformed configuration: {}", reason),
+      BuilderError::UnknownActorType(actor_type)   => write!(f, "no factory is registered for actor type {}", actor_type),
+      BuilderError::Factory { actor_type, reason } => write!(f, "could not construct {}: {}", actor_type, reason),
+      BuilderError::Registration { index, actor_type, error } => {
+        write!(f, "actor {} ({}) refused registration: {}", index, actor_type, error.0)
+      }
+    }
+  }
+}
+
+impl std::error::Error for BuilderError {}
+
+pub struct SimulationBuilder<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  factories: HashMap<String, ActorFactory<Message, Topic>>,
+}
+
+impl<Message, Topic> Default for SimulationBuilder<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  fn default() -> Self {
+    SimulationBuilder { factories: HashMap::new() }
+  }
+}
+
+impl<Message, Topic> SimulationBuilder<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Uses `factory` to construct the actors whose `type` is `actor_type`.
+  pub fn register_factory<F>(mut self, actor_type: &str, factory: F) -> Self
+      where F: Fn(&Table) -> Result<RcActor<Message, Topic>, String> + 'static
+  {
+    self.factories.insert(actor_type.to_string(), Box::new(factory));
+    self
+  }
+
+  /// Builds a `Router` with the actors listed in the TOML file at `path`.
+  pub fn from_toml(&self, path: &Path) -> Result<Router<Message, Topic>, BuilderError> {
+    let text = std::fs::read_to_string(path).map_err(BuilderError::Io)?;
+    self.from_toml_str(&text)
+  }
+
+  /// Builds a `Router` with the actors listed in `text`.
+  pub fn from_toml_str(&self, text: &str) -> Result<Router<Message, Topic>, BuilderError> {
+    let config: Table = toml::from_str(text).map_err(BuilderError::Parse)?;
+    let actors = match config.get("actor") {
+      Some(actors) => actors.as_array().ok_or_else(|| BuilderError::Malformed("`actor` must be an array of tables".to_string()))?.as_slice(),
+      None         => &[],
+    };
+
+    let mut router = Router::new();
+    for (index, parameters) in actors.iter().enumerate() {
+      let parameters = parameters
+        .as_table()
+        .ok_or_else(|| BuilderError::Malformed(format!("actor {} is not a table", index)))?;
+      let actor_type = parameters
+        .get("type")
+        .and_then(|actor_type| actor_type.as_str())
+        .ok_or_else(|| BuilderError::Malformed(format!("actor {} has no `type`", index)))?;
+      let factory    = self
+        .factories
+        .get(actor_type)
+        .ok_or_else(|| BuilderError::UnknownActorType(actor_type.to_string()))?;
+
+      let actor = factory(parameters).map_err(|reason| BuilderError::Factory { actor_type: actor_type.to_string(), reason })?;
+      router
+        .add_actor(actor)
+        .map_err(|error| BuilderError::Registration { index, actor_type: actor_type.to_string(), error })?;
+    }
+
+    Ok(router)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    actor::{Actor, ActorHandle, ActorResult, Registration},
+    message::{Channel, Envelope},
+    rc_cell
+  };
+
+  /// Subscribes to the topic given in its configuration.
+  struct Listener {
+    topic: u8,
+  }
+
+  impl Actor<u32, u8> for Listener {
+    fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+      Ok(vec![])
+    }
+
+    fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+      Ok((vec![Channel::Topic(self.topic)], vec![]))
+    }
+  }
+
+  fn builder() -> SimulationBuilder<u32, u8> {
+    SimulationBuilder::new().register_factory("Listener", |parameters| {
+      let topic = parameters.get("topic").and_then(|topic| topic.as_integer()).ok_or("missing topic")?;
+      Ok(rc_cell!(Listener { topic: topic as u8 }))
+    })
+  }
+
+  #[test]
+  fn actors_are_built_in_order() {
+    let router = builder().from_toml_str(r#"
+      [[actor]]
+      type  = "Listener"
+      topic = 1
+
+      [[actor]]
+      type  = "Listener"
+      topic = 2
+    "#).unwrap();
+
+    assert_eq!(router.actors().len(), 2);
+    assert_eq!(router.subscribe_count(&Channel::Topic(2)), 1);
+  }
+
+  #[test]
+  fn bad_configurations_are_reported() {
+    let error = |text| builder().from_toml_str(text).err().unwrap();
+
+    assert!(matches!(error("actor = ["), BuilderError::Parse(_)));
+    assert!(matches!(error("actor = 1"), BuilderError::Malformed(_)));
+    assert!(matches!(error("[[actor]]\ntopic = 1"), BuilderError::Malformed(_)));
+    assert!(matches!(error("[[actor]]\ntype = \"Speaker\""), BuilderError::UnknownActorType(actor_type) if actor_type == "Speaker"));
+    assert!(matches!(error("[[actor]]\ntype = \"Listener\""), BuilderError::Factory { .. }));
+  }
+}
@@ -0,0 +1,147 @@
+/*!
+
+A `Clock` paces the event loop. The `Timeline` always tracks simulated time; the `Clock`
+decides how simulated time relates to real time. With the default `SimulatedClock` the
+`Router` processes events as fast as it can. With a `WallClock` it waits until the
+real-world moment corresponding to each event's time before processing it.
+
+*/
+
+use std::{
+  thread,
+  time::{Duration, Instant}
+};
+
+use crate::timeline::Time;
+
+/// A boxed `Clock`. With the `send` feature the clock must be `Send` so that the `Router` is.
+#[cfg(not(feature = "send"))]
+pub type BoxedClock = Box<dyn Clock>;
+#[cfg(feature = "send")]
+pub type BoxedClock = Box<dyn Clock + Send>;
+
+pub trait Clock {
+  /// The current time according to this clock.
+  fn now(&self) -> Time;
+
+  /// Blocks until this clock reaches time `t`. Returns immediately if `t` is in the past.
+  fn sleep_until(&mut self, t: Time);
+}
+
+/// Runs the simulation at maximum speed: time jumps straight to each event.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SimulatedClock {
+  now: Time,
+}
+
+impl Clock for SimulatedClock {
+  fn now(&self) -> Time {
+    self.now
+  }
+
+  fn sleep_until(&mut self, t: Time) {
+    self.now = self.now.max(t);
+  }
+}
+
+/// Runs the simulation in real time: one unit of simulated time lasts
+/// `seconds_per_unit` seconds of wall-clock time, measured from when the clock was created.
+#[derive(Copy, Clone, Debug)]
+pub struct WallClock {
+  start           : Instant,
+  seconds_per_unit: f64,
+}
+
+impl WallClock {
+  pub fn new(seconds_per_unit: f64) -> Self {
+    assert!(seconds_per_unit > 0.0, "`seconds_per_unit` must be positive");
+    WallClock {
+      start: Instant::now(),
+      seconds_per_unit,
+    }
+  }
+}
+
+impl Clock for WallClock {
+  fn now(&self) -> Time {
+    (self.start.elapsed().as_secs_f64() / self.seconds_per_unit).into()
+  }
+
+  fn sleep_until(&mut self, t: Time) {
+    let target = self.start + Duration::from_secs_f64(t.0.max(0.0) * self.seconds_per_unit);
+    let now    = Instant::now();
+    if target > now {
+      thread::sleep(target - now);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use crate::{
+    message::{Channel, Envelope, RcEnvelope},
+    router::Router
+  };
+  use super::*;
+
+  /// Records every time it is asked to sleep until, without sleeping. Shared through an `Arc`
+  /// so that the clock is `Send` with the `send` feature.
+  struct RecordingClock {
+    now   : Time,
+    sleeps: Arc<Mutex<Vec<Time>>>,
+  }
+
+  impl Clock for RecordingClock {
+    fn now(&self) -> Time {
+      self.now
+    }
+
+    fn sleep_until(&mut self, t: Time) {
+      self.sleeps.lock().unwrap().push(t);
+      self.now = self.now.max(t);
+    }
+  }
+
+  fn event(message: u32) -> RcEnvelope<u32, u8> {
+    RcEnvelope::new(Envelope::new(0, Channel::General, Some(message), None))
+  }
+
+  #[test]
+  fn simulated_clock_jumps_without_sleeping() {
+    let mut clock = SimulatedClock::default();
+    assert_eq!(clock.now(), Time::from(0.0));
+
+    let start = Instant::now();
+    clock.sleep_until(Time::from(1.0e9));
+    assert_eq!(clock.now(), Time::from(1.0e9));
+    // Time never runs backwards.
+    clock.sleep_until(Time::from(5.0));
+    assert_eq!(clock.now(), Time::from(1.0e9));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The router's time follows the timeline, however far apart the events are.
+    let mut router = Router::<u32, u8>::new();
+    router.push_timeline_event(Time::from(1.0e6), event(1));
+    router.push_timeline_event(Time::from(1.0e9), event(2));
+    let start = Instant::now();
+    router.run();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(router.now(), Time::from(1.0e9));
+  }
+
+  #[test]
+  fn router_sleeps_until_each_event_in_order() {
+    let sleeps     = Arc::new(Mutex::new(vec![]));
+    let clock      = RecordingClock { now: Time::default(), sleeps: sleeps.clone() };
+    let mut router = Router::<u32, u8>::new().with_clock(Box::new(clock));
+    router.push_timeline_event(Time::from(3.0), event(3));
+    router.push_timeline_event(Time::from(1.0), event(1));
+    router.push_timeline_event(Time::from(2.0), event(2));
+    router.run();
+
+    let expected: Vec<Time> = [1.0, 2.0, 3.0].into_iter().map(Time::from).collect();
+    assert_eq!(*sleeps.lock().unwrap(), expected);
+  }
+}
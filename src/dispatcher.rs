@@ -0,0 +1,89 @@
+/*!
+
+A `Dispatcher` decides how a message is fanned out to the actors subscribed to its
+channel. The `Router` looks up the subscribers and hands them to its `Dispatcher`, which
+delivers the envelope and collects each subscriber's result.
+
+*/
+
+use std::fmt::Debug;
+
+use rand::{
+  rngs::SmallRng,
+  seq::SliceRandom,
+  SeedableRng
+};
+
+use crate::{
+  actor::{ActorHandle, ActorResult, RcActor},
+  message::{BoundedTopic, RcEnvelope}
+};
+
+pub trait Dispatcher<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  /// Delivers `envelope` to each of `subscribers`, looking the actors up in `actors` by
+  /// handle, and returns each subscriber's result in the order they were produced.
+  fn dispatch(
+    &mut self,
+    subscribers: &[ActorHandle],
+    actors     : &[RcActor<Message, Topic>],
+    envelope   : RcEnvelope<Message, Topic>
+  ) -> Vec<(ActorHandle, ActorResult<Message, Topic>)>;
+}
+
+/// Delivers to subscribers in the order they subscribed. This is the default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SequentialDispatcher;
+
+impl<Message, Topic> Dispatcher<Message, Topic> for SequentialDispatcher
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  fn dispatch(
+    &mut self,
+    subscribers: &[ActorHandle],
+    actors     : &[RcActor<Message, Topic>],
+    envelope   : RcEnvelope<Message, Topic>
+  ) -> Vec<(ActorHandle, ActorResult<Message, Topic>)> {
+    subscribers
+      .iter()
+      .map(|&handle| {
+        let mut receiver = actors[handle as usize].borrow_mut();
+        (handle, receiver.receive_message(&envelope))
+      })
+      .collect()
+  }
+}
+
+/// Delivers to subscribers in a random order. Useful for checking that a stochastic model
+/// does not accidentally depend on the order in which actors were registered.
+pub struct ShuffledDispatcher {
+  rng: SmallRng,
+}
+
+impl ShuffledDispatcher {
+  pub fn new(seed: u64) -> Self {
+    ShuffledDispatcher {
+      rng: SmallRng::seed_from_u64(seed),
+    }
+  }
+}
+
+impl<Message, Topic> Dispatcher<Message, Topic> for ShuffledDispatcher
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  fn dispatch(
+    &mut self,
+    subscribers: &[ActorHandle],
+    actors     : &[RcActor<Message, Topic>],
+    envelope   : RcEnvelope<Message, Topic>
+  ) -> Vec<(ActorHandle, ActorResult<Message, Topic>)> {
+    let mut shuffled = subscribers.to_vec();
+    shuffled.shuffle(&mut self.rng);
+
+    SequentialDispatcher.dispatch(&shuffled, actors, envelope)
+  }
+}
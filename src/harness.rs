@@ -0,0 +1,110 @@
+/*!
+
+An `ActorTestHarness` drives a single `Actor` without a `Router`, so an actor's behavior can
+be unit tested in isolation. The harness registers the actor, remembers what it subscribed
+to and what it sent on registration, and delivers messages to it directly.
+
+```rust
+# use actor_model::{
+#   actor::{Actor, ActorHandle, ActorResult, Registration},
+#   harness::ActorTestHarness,
+#   message::{Channel, Envelope, RcEnvelope},
+#   rc_cell
+# };
+struct Echo;
+
+impl Actor<u32, ()> for Echo {
+  fn receive_message(&mut self, envelope: &Envelope<u32, ()>) -> ActorResult<u32, ()> {
+    Ok(vec![RcEnvelope::new(envelope.clone())])
+  }
+
+  fn register(&mut self, _handle: ActorHandle) -> Registration<u32, ()> {
+    Ok((vec![Channel::General], vec![]))
+  }
+}
+
+let mut harness = ActorTestHarness::new(rc_cell!(Echo));
+assert!(harness.is_subscribed(&Channel::General));
+
+let envelope  = RcEnvelope::new(Envelope::new(1, Channel::General, Some(7), None));
+let responses = harness.send(envelope).unwrap();
+assert_eq!(responses[0].message, Some(7));
+```
+
+*/
+
+use std::fmt::Debug;
+
+use crate::{
+  actor::{ActorHandle, ActorResult, RcActor},
+  message::{BoundedTopic, Channel, RcEnvelope}
+};
+
+pub struct ActorTestHarness<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  actor           : RcActor<Message, Topic>,
+  handle          : ActorHandle,
+  subscriptions   : Vec<Channel<Topic>>,
+  initial_messages: Vec<RcEnvelope<Message, Topic>>,
+}
+
+impl<Message, Topic> ActorTestHarness<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  /// Registers `actor` with handle 0.
+  ///
+  /// Panics if the actor refuses registration.
+  pub fn new(actor: RcActor<Message, Topic>) -> Self {
+    Self::with_handle(actor, 0)
+  }
+
+  /// Registers `actor` with the given handle.
+  ///
+  /// Panics if the actor refuses registration.
+  pub fn with_handle(actor: RcActor<Message, Topic>, handle: ActorHandle) -> Self {
+    let (subscriptions, initial_messages) = actor
+      .borrow_mut()
+      .register(handle)
+      .unwrap_or_else(|error| panic!("{} refused registration: {}", actor.borrow().name(), error.0));
+
+    ActorTestHarness {
+      actor,
+      handle,
+      subscriptions,
+      initial_messages,
+    }
+  }
+
+  /// Delivers `envelope` to the actor, whether or not it subscribed to the envelope's
+  /// channel, and returns its result. Unlike the `Router`, the harness does not act on errors.
+  pub fn send(&mut self, envelope: RcEnvelope<Message, Topic>) -> ActorResult<Message, Topic> {
+    self.actor.borrow_mut().receive_message(&envelope)
+  }
+
+  /// The handle the actor was registered with.
+  pub fn handle(&self) -> ActorHandle {
+    self.handle
+  }
+
+  /// The actor under test.
+  pub fn actor(&self) -> &RcActor<Message, Topic> {
+    &self.actor
+  }
+
+  /// The channels the actor subscribed to when it was registered.
+  pub fn subscriptions(&self) -> &[Channel<Topic>] {
+    &self.subscriptions
+  }
+
+  pub fn is_subscribed(&self, channel: &Channel<Topic>) -> bool {
+    self.subscriptions.contains(channel)
+  }
+
+  /// The messages the actor sent when it was registered.
+  pub fn initial_messages(&self) -> &[RcEnvelope<Message, Topic>] {
+    &self.initial_messages
+  }
+}
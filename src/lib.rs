@@ -2,14 +2,21 @@ pub mod timeline;
 pub mod message;
 pub mod actor;
 pub mod router;
+pub mod dispatcher;
+pub mod clock;
+pub mod harness;
+pub mod debug;
 pub mod rccell;
+#[cfg(feature = "toml")]
+pub mod builder;
+#[cfg(feature = "remote")]
+pub mod remote;
 
 
 
 #[cfg(test)]
 mod tests {
   use crate::router::Router;
-  use super::*;
 
   #[test]
   fn it_works() {
@@ -11,19 +11,24 @@ content of the message (`MessagePayload`) be three layers deep might be overkill
 */
 
 use std::{
+  borrow::Cow,
   fmt::Debug,
-  rc::Rc,
   hash::Hash
 };
+#[cfg(not(feature = "send"))]
+use std::rc::Rc;
 
 use crate::{
   actor::ActorHandle,
-  timeline::Time
+  timeline::{ScheduleMode, Time, TimelineId, MAIN_TIMELINE}
 };
 
 // Envelopes and messages should generally be immutable, as multiple actors
 // will potentially access them.
+#[cfg(not(feature = "send"))]
 pub type RcEnvelope<M, T> = Rc<Envelope<M, T>>;
+#[cfg(feature = "send")]
+pub type RcEnvelope<M, T> = std::sync::Arc<Envelope<M, T>>;
 
 /// The `Channel` struct below is parameterized by `Topic` which as a lot of trait
 /// bounds. Instead of listing all the bounds everywhere, we just require `Topic`
@@ -39,24 +44,92 @@ impl<T> BoundedTopic for T where T: Copy + Clone + Debug + PartialEq + Eq + Hash
 /// `Channel`s are the recipient's of messages (`Envelope`s). You could conceivably
 /// just have a `Topic` generic, but having a parameterized `Channel` guarantees
 /// variants for timeline-related messages.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// `Channel` is not `Copy`, because some variants wrap other channels.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Channel<Topic>
     where Topic: BoundedTopic
 {
   // System Messages
   Stop,  // Request early exit
   Debug, // Request command line
+  SimulationStarted, // Emitted by the `Router` the first time `run()` is called
+  Pause,  // Halt the event loop until resumed (see `Router::pause_handle()`)
+  Resume, // Continue after a `Pause`
+  Echo,   // Sent back to the sender on `Channel::Actor(sender)`, e.g. as a ping
+  /// Delivered to every remaining actor, subscribed or not, when the actor with the given
+  /// handle is removed (see `Router::remove_actor()`).
+  Tombstone(ActorHandle),
+  /// Emitted by the `Router` when it detects a suspicious but non-fatal condition. Subscribe
+  /// to `Channel::warnings()` to receive every warning, whatever its text.
+  Warning(Cow<'static, str>),
 
   // Timeline-related Messages
   TimelineEvent, // Emitted by `Timeline`. Could choose to allow topic in here, too.
+  Heartbeat,     // Delivered to every actor at regular intervals (see `Router::with_heartbeat()`)
   ScheduleEvent, // Request to schedule an event
   Time,          // Time request and answer
 
   // Channels used by client code. This is the mechanism by which we extend `Channel`.
   Topic(Topic),
+  /// Subscription only: returned from `Actor::register()`, it subscribes the actor to
+  /// `Channel::Topic(topic)` for each of the topics. Nothing is delivered on this channel.
+  BulkTopic(Vec<Topic>),
+
+  // Routing patterns
+  /// Delivered to every actor, in order of handle, whatever it subscribes to, e.g. for
+  /// simulation-wide announcements like resetting statistics (see `Router::broadcast()`).
+  Broadcast,
+  /// A request delivered to the subscribers of `inner`. The `Router` re-addresses every
+  /// response to `reply_to`, so responders don't need to know where the reply goes.
+  RequestReply {
+    inner   : Box<Channel<Topic>>,
+    reply_to: Box<Channel<Topic>>,
+  },
+  /// Delivered on `inner` only if the predicate registered under `predicate_id` (see
+  /// `Router::register_predicate()`) accepts the envelope, and otherwise dropped.
+  Conditional {
+    inner       : Box<Channel<Topic>>,
+    predicate_id: u32,
+  },
+  /// Delivered on `inner` after `delay_steps` more routing steps, i.e. once the `Router` has
+  /// processed that many more messages or events. With a delay of 0 it is enqueued on `inner`
+  /// right away.
+  Defer {
+    delay_steps: usize,
+    inner      : Box<Channel<Topic>>,
+  },
+  /// Delivered to the subscribers of `Channel::Aggregate(topic)`. The `Router` holds their
+  /// responses until every subscriber has answered, then routes them together as one batch,
+  /// ahead of anything already in the message queue (see `AggregateBarrier`).
+  Aggregate(Topic),
+  /// Delivered to `fanout` of the actors subscribed to `Channel::gossip()`, chosen at random
+  /// without replacement, or to all of them if there are no more than `fanout`. For
+  /// gossip-protocol-style architectures in which not every subscriber hears every message.
+  Gossip {
+    fanout: usize,
+  },
+  /// Delivered to one of the actors subscribed to `Channel::RoundRobin(topic)`, taking turns
+  /// in the order they subscribed, so successive messages spread the load across subscribers.
+  RoundRobin(Topic),
+  /// Delivered to the first `n` of the actors subscribed to `Channel::Topic(topic)`, in the
+  /// order they subscribed. `Channel::Exactly(1, topic)` has a single consumer; with
+  /// `usize::MAX` it is the same as `Channel::Topic(topic)`.
+  Exactly(usize, Topic),
 
   // Just some examples
   Actor(ActorHandle),
+  /// Delivered to exactly the listed actors, in order, whatever they subscribe to. Cheaper
+  /// than a subscription lookup when the recipients are known when the message is created.
+  Multicast(Vec<ActorHandle>),
+  /// Delivered to exactly the given actor, whatever it subscribes to. Subject to
+  /// `Router::with_per_actor_queue_limit()`.
+  Unicast(ActorHandle),
+  /// Delivered straight to the given actor, whatever it subscribes to, e.g. for a request to
+  /// an actor whose handle is known. Unlike `Channel::Unicast`, it is never held back by
+  /// `Router::with_per_actor_queue_limit()`.
+  Direct(ActorHandle),
   General,       // Catch all
 }
 
@@ -66,9 +139,39 @@ impl<Topic> Channel<Topic>
   pub fn with_topic<T: Into<Topic>>(self, topic: T) -> Self {
     Channel::Topic(topic.into())
   }
+
+  /// The channel to subscribe to in order to receive every `Channel::Warning`.
+  pub fn warnings() -> Self {
+    Channel::Warning(Cow::Borrowed(""))
+  }
+
+  /// The channel to subscribe to in order to take part in `Channel::Gossip`, whatever the fanout.
+  pub fn gossip() -> Self {
+    Channel::Gossip { fanout: 0 }
+  }
+
+  /// The channels that subscribing to this channel subscribes to: the individual topics of a
+  /// `Channel::BulkTopic`, or else just this channel.
+  pub(crate) fn into_subscriptions(self) -> Vec<Self> {
+    match self {
+      Channel::BulkTopic(topics) => topics.into_iter().map(Channel::Topic).collect(),
+      channel                    => vec![channel],
+    }
+  }
+
+  /// The channel whose subscribers receive messages sent on this channel.
+  pub(crate) fn subscription_key(&self) -> Self {
+    match self {
+      Channel::Warning(_)        => Channel::warnings(),
+      Channel::Gossip { .. }     => Channel::gossip(),
+      Channel::Exactly(_, topic) => Channel::Topic(*topic),
+      channel                    => channel.clone(),
+    }
+  }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Envelope<Message, Topic>
     where Topic: BoundedTopic,
           Message: Clone + Debug
@@ -76,5 +179,87 @@ pub struct Envelope<Message, Topic>
   pub from   : ActorHandle,
   pub channel: Channel<Topic>,
   pub message: Option<Message>,
-  pub time   : Option<Time>
+  /// For a timed event, the time it happens. Otherwise the `Router` sets it to the current
+  /// time when the envelope is routed.
+  pub time   : Time,
+  /// Whether `time` was chosen by the sender, e.g. the time of a scheduled event or of a
+  /// status transition, rather than filled in by the `Router`.
+  pub is_timed_event: bool,
+  /// Whether `time` is absolute or relative to the time the envelope is routed. Only
+  /// meaningful for `Channel::ScheduleEvent`.
+  pub schedule_mode: ScheduleMode,
+  /// The timeline a `Channel::ScheduleEvent` envelope schedules its event on, and the
+  /// timeline a `Channel::TimelineEvent` envelope's event fired on.
+  pub timeline_id: TimelineId,
+  /// Assigned by the `Router` when the envelope is routed. IDs increase in routing order.
+  pub message_id: Option<u64>,
+  /// With causal tracking (see `Router::with_causal_tracking()`), the `message_id` of the
+  /// message that caused this one, e.g. the message an actor was responding to.
+  pub correlation_id: Option<u64>,
+  /// A human-readable description, e.g. of the event a `Channel::ScheduleEvent` envelope
+  /// schedules. Only used for debug output, so it is not serialized.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub description: Option<&'static str>,
+  /// A short identifier for the event a `Channel::ScheduleEvent` envelope schedules, e.g.
+  /// `"attempt_infection"`. Like `description`, only used for diagnostics and not serialized.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub name: Option<&'static str>,
+  /// Envelopes bundled with this one, e.g. the individual changes of a batch. The `Router`
+  /// enqueues them after delivering this envelope, ahead of the responses to it, and strips
+  /// them from responses that copied them with `..envelope.clone()`. Empty by default.
+  pub children: Vec<RcEnvelope<Message, Topic>>,
+}
+
+impl<Message, Topic> Envelope<Message, Topic>
+    where Topic: BoundedTopic,
+          Message: Clone + Debug
+{
+  /// Passing `Some(time)` makes the envelope a timed event at `time`. With `None`, the
+  /// `Router` fills in the time when the envelope is routed.
+  pub fn new(from: ActorHandle, channel: Channel<Topic>, message: Option<Message>, time: Option<Time>) -> Self {
+    Envelope {
+      from,
+      channel,
+      message,
+      time          : time.unwrap_or_default(),
+      is_timed_event: time.is_some(),
+      schedule_mode : ScheduleMode::Absolute,
+      timeline_id   : MAIN_TIMELINE,
+      message_id    : None,
+      correlation_id: None,
+      description   : None,
+      name          : None,
+      children      : vec![],
+    }
+  }
+
+  /// Makes a `Channel::ScheduleEvent` envelope's time relative to the time it is routed, so
+  /// `Some(delta)` schedules the event `delta` time units into the future.
+  pub fn relative_to_now(mut self) -> Self {
+    self.schedule_mode = ScheduleMode::RelativeToNow;
+    self
+  }
+
+  /// Schedules a `Channel::ScheduleEvent` envelope's event on the given timeline instead of
+  /// the main one.
+  pub fn on_timeline(mut self, timeline_id: TimelineId) -> Self {
+    self.timeline_id = timeline_id;
+    self
+  }
+
+  pub fn with_description(mut self, description: &'static str) -> Self {
+    self.description = Some(description);
+    self
+  }
+
+  pub fn with_name(mut self, name: &'static str) -> Self {
+    self.name = Some(name);
+    self
+  }
+
+  /// Bundles `children` with the envelope, to be enqueued once it has been delivered.
+  pub fn with_children(mut self, children: Vec<RcEnvelope<Message, Topic>>) -> Self {
+    self.children = children;
+    self
+  }
 }
@@ -31,33 +31,57 @@ assert!(d.upgrade().is_some());  // Use the upgrade method to get a RcCell point
 
 `RcCell<T>` structs implement the `Hash` trait by using the value of their inner `Rc` pointer value.
 
+With the `send` feature, `RcCell<T>` wraps an `Arc<Mutex<T>>` instead, so that it is `Send`
+when `T` is. The API is the same, except that borrowing a value that is already borrowed
+blocks instead of panicking, and `AsRef` is not available.
+
 */
 
 use std::{
-  cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut},
   cmp::PartialEq,
   fmt::Debug,
   hash::{Hash, Hasher},
   ops::Deref,
   pin::Pin,
-  rc::{Rc, Weak},
+};
+#[cfg(not(feature = "send"))]
+use std::cell::{BorrowError, BorrowMutError};
+#[cfg(feature = "send")]
+use std::sync::{MutexGuard, TryLockError};
+
+#[cfg(not(feature = "send"))]
+#[doc(hidden)]
+pub use std::{
+  cell::{Ref as ReadGuard, RefCell as Cell, RefMut as WriteGuard},
+  rc::{Rc as Shared, Weak},
+};
+#[cfg(feature = "send")]
+#[doc(hidden)]
+pub use std::sync::{
+  Arc as Shared,
+  Mutex as Cell,
+  MutexGuard as ReadGuard,
+  MutexGuard as WriteGuard,
+  Weak,
 };
 
-/// Wrapper for `Rc<RefCell<T>>`.
-#[derive(Debug, Default, Eq)]
-pub struct RcCell<T: ?Sized>(pub Rc<RefCell<T>>);
+/// Wrapper for `Rc<RefCell<T>>` (or `Arc<Mutex<T>>` with the `send` feature).
+#[derive(Debug, Default)]
+pub struct RcCell<T: ?Sized>(pub Shared<Cell<T>>);
 
+/// Wraps `$obj` in an `RcCell`. Unlike `RcCell::new()`, the result can be coerced to an
+/// `RcCell` of a trait object, e.g. an `RcActor`.
 #[macro_export]
 macro_rules! rc_cell {
   ($obj:expr) => {
-    RcCell(Rc::new(RefCell::new($obj)))
+    $crate::rccell::RcCell($crate::rccell::Shared::new($crate::rccell::Cell::new($obj)))
   };
 }
 
 pub use rc_cell;
 
 impl<T> RcCell<T> {
-  /// Similar to [Rc::try_unwrap].
+  /// Similar to [std::rc::Rc::try_unwrap].
   /// Returns the inner value if the `RefCell` has only one strong reference.
   /// Otherwise, it returns an `Err` with the same `RefCell` that was passed in.
   /// Note that this function success even if there are multiple weak references.
@@ -73,7 +97,12 @@ impl<T> RcCell<T> {
   /// assert!(RcCell::try_unwrap(x).is_err());
   /// ```
   pub fn try_unwrap(self) -> Result<T, Self> {
-    Rc::try_unwrap(self.0).map(RefCell::into_inner).map_err(Self)
+    #[cfg(not(feature = "send"))]
+    return Shared::try_unwrap(self.0).map(Cell::into_inner).map_err(Self);
+    #[cfg(feature = "send")]
+    return Shared::try_unwrap(self.0)
+        .map(|cell| cell.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .map_err(Self);
   }
 
   /// Constructs a new `RcCell<T>`.
@@ -84,12 +113,12 @@ impl<T> RcCell<T> {
   /// let x = RcCell::new(1);
   /// ```
   pub fn new(value: T) -> Self {
-    Self(Rc::new(RefCell::new(value)))
+    Self(Shared::new(Cell::new(value)))
   }
 }
 
 impl<T: ?Sized> RcCell<T> {
-  /// Similar to [Rc::downgrade].
+  /// Similar to [std::rc::Rc::downgrade].
   /// Creates a new [WeakCell] pointer to this allocation.
   /// # Examples
   /// ```rust
@@ -99,10 +128,10 @@ impl<T: ?Sized> RcCell<T> {
   /// let weak_five = x.downgrade();
   /// ```
   pub fn downgrade(&self) -> WeakCell<T> {
-    WeakCell(Rc::downgrade(&self.0))
+    WeakCell(Shared::downgrade(&self.0))
   }
 
-  /// Similar to [Rc::weak_count].
+  /// Similar to [std::rc::Rc::weak_count].
   /// Gets the number of [WeakCell] pointers to this allocation.
   /// # Examples
   /// ```rust
@@ -114,10 +143,10 @@ impl<T: ?Sized> RcCell<T> {
   /// assert_eq!(RcCell::weak_count(&x), 1);
   /// ```
   pub fn weak_count(this: &Self) -> usize {
-    Rc::weak_count(&this.0)
+    Shared::weak_count(&this.0)
   }
 
-  /// Similar to [Rc::strong_count].
+  /// Similar to [std::rc::Rc::strong_count].
   /// Gets the number of strong ([RcCell]) pointers to this allocation.
   /// # Examples
   /// ```rust
@@ -129,10 +158,10 @@ impl<T: ?Sized> RcCell<T> {
   /// assert_eq!(RcCell::strong_count(&x), 2);
   /// ```
   pub fn strong_count(this: &Self) -> usize {
-    Rc::strong_count(&this.0)
+    Shared::strong_count(&this.0)
   }
 
-  /// Similar to [Rc::ptr_eq].
+  /// Similar to [std::rc::Rc::ptr_eq].
   /// Returns `true` if two `RcCell`s point to the same allocation.
   /// # Examples
   /// ```rust
@@ -146,11 +175,11 @@ impl<T: ?Sized> RcCell<T> {
   /// assert!(!RcCell::ptr_eq(&x, &y));
   /// ```
   pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-    Rc::ptr_eq(&this.0, &other.0)
+    Shared::ptr_eq(&this.0, &other.0)
   }
 
-  /// Similar to [RefCell::try_borrow].
-  /// Returns a [Ref] to the inner value if there is no [RefMut] pointing to the same allocation.
+  /// Similar to [std::cell::RefCell::try_borrow].
+  /// Returns a [std::cell::Ref] to the inner value if there is no [std::cell::RefMut] pointing to the same allocation.
   /// Otherwise, it returns a `BorrowError`.
   /// # Examples
   /// ```rust
@@ -161,12 +190,19 @@ impl<T: ?Sized> RcCell<T> {
   /// let x_ref = x.try_borrow();
   /// assert!(x_ref.is_ok());
   /// ```
-  pub fn try_borrow(&self) -> Result<Ref<T>, BorrowError> {
+  #[cfg(not(feature = "send"))]
+  pub fn try_borrow(&self) -> Result<ReadGuard<'_, T>, BorrowError> {
     self.0.try_borrow()
   }
 
-  /// Similar to [RefCell::try_borrow_mut].
-  /// Returns a [RefMut] to the inner value if there is no [RefMut] nor [Ref] pointing to the same allocation.
+  /// Similar to [std::sync::Mutex::try_lock].
+  #[cfg(feature = "send")]
+  pub fn try_borrow(&self) -> Result<ReadGuard<'_, T>, TryLockError<MutexGuard<'_, T>>> {
+    self.0.try_lock()
+  }
+
+  /// Similar to [std::cell::RefCell::try_borrow_mut].
+  /// Returns a [std::cell::RefMut] to the inner value if there is no [std::cell::RefMut] nor [std::cell::Ref] pointing to the same allocation.
   /// Otherwise, it returns a `BorrowMutError`.
   /// # Examples
   /// ```rust
@@ -177,12 +213,19 @@ impl<T: ?Sized> RcCell<T> {
   /// let mut x_ref = x.try_borrow_mut();
   /// assert!(x_ref.is_ok());
   /// ```
-  pub fn try_borrow_mut(&self) -> Result<RefMut<T>, BorrowMutError> {
+  #[cfg(not(feature = "send"))]
+  pub fn try_borrow_mut(&self) -> Result<WriteGuard<'_, T>, BorrowMutError> {
     self.0.try_borrow_mut()
   }
 
-  /// Similar to [RefCell::borrow].
-  /// Returns a [Ref] to the inner value if there is no [RefMut] pointing to the same allocation.
+  /// Similar to [std::sync::Mutex::try_lock].
+  #[cfg(feature = "send")]
+  pub fn try_borrow_mut(&self) -> Result<WriteGuard<'_, T>, TryLockError<MutexGuard<'_, T>>> {
+    self.0.try_lock()
+  }
+
+  /// Similar to [std::cell::RefCell::borrow].
+  /// Returns a [std::cell::Ref] to the inner value if there is no [std::cell::RefMut] pointing to the same allocation.
   /// Otherwise, it panics.
   /// # Examples
   /// ```rust
@@ -191,12 +234,15 @@ impl<T: ?Sized> RcCell<T> {
   /// let x = RcCell::new(1);
   /// let x_ref = x.borrow();
   /// ```
-  pub fn borrow(&self) -> Ref<T> {
-    self.0.borrow()
+  pub fn borrow(&self) -> ReadGuard<'_, T> {
+    #[cfg(not(feature = "send"))]
+    return self.0.borrow();
+    #[cfg(feature = "send")]
+    return self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
   }
 
-  /// Similar to [RefCell::borrow_mut].
-  /// Returns a [RefMut] to the inner value if there is no [RefMut] nor [Ref] pointing to the same allocation.
+  /// Similar to [std::cell::RefCell::borrow_mut].
+  /// Returns a [std::cell::RefMut] to the inner value if there is no [std::cell::RefMut] nor [std::cell::Ref] pointing to the same allocation.
   /// Otherwise, it panics.
   /// # Examples
   /// ```rust
@@ -205,8 +251,11 @@ impl<T: ?Sized> RcCell<T> {
   /// let x = RcCell::new(1);
   /// let x_ref = x.borrow_mut();
   /// ```
-  pub fn borrow_mut(&self) -> RefMut<T> {
-    self.0.borrow_mut()
+  pub fn borrow_mut(&self) -> WriteGuard<'_, T> {
+    #[cfg(not(feature = "send"))]
+    return self.0.borrow_mut();
+    #[cfg(feature = "send")]
+    return self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
   }
 }
 
@@ -217,6 +266,7 @@ impl<T: Unpin> RcCell<T> {
   }
 }
 
+#[cfg(not(feature = "send"))]
 impl<T: ?Sized> AsRef<T> for RcCell<T> {
   fn as_ref(&self) -> &T {
     unsafe { self.0.as_ref().try_borrow_unguarded().unwrap() }
@@ -224,7 +274,7 @@ impl<T: ?Sized> AsRef<T> for RcCell<T> {
 }
 
 impl<T: ?Sized> Deref for RcCell<T> {
-  type Target = RefCell<T>;
+  type Target = Cell<T>;
 
   /// `RefCell<T>` does not implement `Deref`, and borrowing its inner value can cause a lot of panic errors.
   /// Therefore, `Deref::deref` will return a reference to the inner `RefCell<T>`.
@@ -237,7 +287,7 @@ impl<T: ?Sized> Hash for RcCell<T> {
   /// `RefCell<T>` does not implement `PartialEq`, and borrowing its inner value can cause a lot of panic errors.
   /// Therefore, `Hash` will only use the value of the `Rc` pointer inside `RefCell<T>`.
   fn hash<H: Hasher>(&self, state: &mut H) {
-    self.0.as_ptr().hash(state);
+    Shared::as_ptr(&self.0).hash(state);
   }
 }
 
@@ -245,10 +295,12 @@ impl<T: ?Sized> PartialEq for RcCell<T> {
   /// The `RefCell<T>` trait does not implement `PartialEq`, and borrowing its inner value can cause a lot of panic
   /// errors. Therefore, `PartialEq` will check that two `RefCell<T>` point to the exact same allocation.
   fn eq(&self, other: &Self) -> bool {
-    RcCell::ptr_eq(&self, &other)
+    RcCell::ptr_eq(self, other)
   }
 }
 
+impl<T: ?Sized> Eq for RcCell<T> {}
+
 
 impl<T: ?Sized> Clone for RcCell<T> {
   fn clone(&self) -> Self {
@@ -259,7 +311,7 @@ impl<T: ?Sized> Clone for RcCell<T> {
 
 /// Version of `RefCell` that holds a non-owning reference to the managed allocation.
 #[derive(Debug, Default)]
-pub struct WeakCell<T: ?Sized>(Weak<RefCell<T>>);
+pub struct WeakCell<T: ?Sized>(Weak<Cell<T>>);
 
 
 impl<T> WeakCell<T> {
@@ -329,7 +381,7 @@ impl<T: ?Sized> Clone for WeakCell<T> {
 // Therefore, `PartialEq` will check that two `RefCell<T>` point to the exact same allocation.
 impl<T: ?Sized> PartialEq for WeakCell<T> {
   fn eq(&self, other: &Self) -> bool {
-    WeakCell::ptr_eq(&self, &other)
+    WeakCell::ptr_eq(self, other)
   }
 }
 
@@ -0,0 +1,259 @@
+/*!
+
+A `RemoteActor` stands in for an actor that lives in another process, possibly on another
+machine, so that a simulation can be split across processes without changing any actor code.
+The other process hands the real actor to `serve()`, and the `Router` adds the stand-in with
+`Router::add_remote_actor()`.
+
+The `RemoteActor` forwards each call to `register()` and `receive_message()` over a
+`TcpStream` and waits for the answer, so a remote actor behaves exactly like a local one, only
+slower. Requests and responses are sent as newline-delimited JSON. An envelope's
+`description` and `name` are not sent.
+
+If the connection fails, the `RemoteActor` answers every message with `ActorError::Fatal`,
+and the `Router` stops delivering messages to it. If it fails during registration, the
+`RemoteActor` refuses registration and is not added.
+
+*/
+
+use std::{
+  fmt::Debug,
+  io::{self, BufRead, BufReader, Write},
+  marker::PhantomData,
+  net::{TcpStream, ToSocketAddrs}
+};
+
+use serde::{
+  de::DeserializeOwned,
+  Deserialize,
+  Serialize
+};
+
+use crate::{
+  actor::{Actor, ActorError, ActorHandle, ActorResult, RcActor, Registration, RegistrationError},
+  message::{BoundedTopic, Channel, Envelope, RcEnvelope}
+};
+
+/// Sent by a `RemoteActor` to the process serving the real actor.
+#[derive(Serialize, Deserialize)]
+enum Request<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  Register(ActorHandle),
+  Message(Envelope<Message, Topic>),
+}
+
+/// A `Registration` with owned envelopes, which can be serialized.
+type SentRegistration<Message, Topic> = Result<(Vec<Channel<Topic>>, Vec<Envelope<Message, Topic>>), RegistrationError>;
+
+/// The served actor's answer to a `Request`.
+#[derive(Serialize, Deserialize)]
+enum Response<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  Registered(SentRegistration<Message, Topic>),
+  Received(Result<Vec<Envelope<Message, Topic>>, ActorError>),
+}
+
+fn send<V: Serialize>(stream: &mut TcpStream, value: &V) -> io::Result<()> {
+  serde_json::to_writer(&mut *stream, value)?;
+  stream.write_all(b"\n")?;
+  stream.flush()
+}
+
+/// Returns `None` if the other end closed the connection.
+fn receive<V: DeserializeOwned>(reader: &mut BufReader<TcpStream>) -> io::Result<Option<V>> {
+  let mut line = String::new();
+  if reader.read_line(&mut line)? == 0 {
+    return Ok(None);
+  }
+  Ok(Some(serde_json::from_str(&line)?))
+}
+
+fn into_envelopes<Message, Topic>(envelopes: Vec<RcEnvelope<Message, Topic>>) -> Vec<Envelope<Message, Topic>>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  envelopes.into_iter().map(RcEnvelope::unwrap_or_clone).collect()
+}
+
+fn into_rc_envelopes<Message, Topic>(envelopes: Vec<Envelope<Message, Topic>>) -> Vec<RcEnvelope<Message, Topic>>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  envelopes.into_iter().map(RcEnvelope::new).collect()
+}
+
+/// Forwards everything it receives to an actor served by `serve()` in another process.
+pub struct RemoteActor<Message, Topic> {
+  writer : TcpStream,
+  reader : BufReader<TcpStream>,
+  name   : String,
+  /// Why the connection failed, if it has.
+  failure: Option<String>,
+  _types : PhantomData<fn() -> (Message, Topic)>,
+}
+
+impl<Message, Topic> RemoteActor<Message, Topic>
+    where Message: Clone + Debug + Serialize + DeserializeOwned,
+          Topic  : BoundedTopic + Serialize + DeserializeOwned
+{
+  /// Connects to an actor served by `serve()` at `addr`.
+  pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+    let writer = TcpStream::connect(addr)?;
+    // Every request waits for its response, so don't let Nagle's algorithm hold requests back.
+    writer.set_nodelay(true)?;
+
+    Ok(RemoteActor {
+      name   : format!("RemoteActor({})", writer.peer_addr()?),
+      reader : BufReader::new(writer.try_clone()?),
+      writer,
+      failure: None,
+      _types : PhantomData,
+    })
+  }
+
+  fn exchange(&mut self, request: &Request<Message, Topic>) -> Result<Response<Message, Topic>, ActorError> {
+    if let Some(failure) = &self.failure {
+      return Err(ActorError::Fatal(failure.clone()));
+    }
+
+    let response = send(&mut self.writer, request).and_then(|_| receive(&mut self.reader));
+    let failure = match response {
+      Ok(Some(response)) => return Ok(response),
+      Ok(None)           => format!("{} closed the connection", self.name),
+      Err(error)         => format!("{}: {}", self.name, error),
+    };
+    self.failure = Some(failure.clone());
+    Err(ActorError::Fatal(failure))
+  }
+}
+
+impl<Message, Topic> Actor<Message, Topic> for RemoteActor<Message, Topic>
+    where Message: Clone + Debug + Serialize + DeserializeOwned,
+          Topic  : BoundedTopic + Serialize + DeserializeOwned
+{
+  fn receive_message(&mut self, envelope: &Envelope<Message, Topic>) -> ActorResult<Message, Topic> {
+    match self.exchange(&Request::Message(envelope.clone()))? {
+      Response::Received(result) => result.map(into_rc_envelopes),
+      Response::Registered(_)    => Err(ActorError::Fatal(format!("{} answered out of turn", self.name))),
+    }
+  }
+
+  /// Refuses registration if the served actor does, or if the connection fails.
+  fn register(&mut self, handle: ActorHandle) -> Registration<Message, Topic> {
+    match self.exchange(&Request::Register(handle)) {
+      Ok(Response::Registered(registration)) => {
+        registration.map(|(subscriptions, messages)| (subscriptions, into_rc_envelopes(messages)))
+      }
+      Ok(Response::Received(_)) => {
+        let failure = format!("{} answered out of turn", self.name);
+        self.failure = Some(failure.clone());
+        Err(RegistrationError(failure))
+      }
+      Err(ActorError::Fatal(failure) | ActorError::Recoverable(failure)) => Err(RegistrationError(failure)),
+    }
+  }
+
+  fn name(&self) -> &str {
+    &self.name
+  }
+}
+
+/// Serves `actor` to the `RemoteActor` connected on `stream` until it disconnects, which
+/// happens when the `Router` that owns it is dropped.
+pub fn serve<Message, Topic>(stream: TcpStream, actor: RcActor<Message, Topic>) -> io::Result<()>
+    where Message: Clone + Debug + Serialize + DeserializeOwned,
+          Topic  : BoundedTopic + Serialize + DeserializeOwned
+{
+  stream.set_nodelay(true)?;
+  let mut writer = stream.try_clone()?;
+  let mut reader = BufReader::new(stream);
+
+  while let Some(request) = receive(&mut reader)? {
+    let response = match request {
+      Request::Register(handle) => {
+        let registration = actor.borrow_mut().register(handle);
+        Response::Registered(registration.map(|(subscriptions, messages)| (subscriptions, into_envelopes(messages))))
+      }
+      Request::Message(envelope) => {
+        let result = actor.borrow_mut().receive_message(&envelope);
+        Response::Received(result.map(into_envelopes))
+      }
+    };
+    send(&mut writer, &response)?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    net::TcpListener,
+    thread
+  };
+
+  use super::*;
+  use crate::{
+    rc_cell,
+    rccell::RcCell,
+    router::Router
+  };
+
+  /// Answers every message on `Channel::General` with twice the message on `Channel::Topic(1)`.
+  struct Doubler;
+
+  impl Actor<u32, u8> for Doubler {
+    fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+      let doubled = envelope.message.map(|message| 2 * message);
+      Ok(vec![RcEnvelope::new(Envelope::new(envelope.from, Channel::Topic(1), doubled, None))])
+    }
+
+    fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+      Ok((vec![Channel::General], vec![]))
+    }
+  }
+
+  /// Records the messages it receives on `Channel::Topic(1)`.
+  struct Collector {
+    received: RcCell<Vec<u32>>,
+  }
+
+  impl Actor<u32, u8> for Collector {
+    fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+      self.received.borrow_mut().extend(envelope.message);
+      Ok(vec![])
+    }
+
+    fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+      Ok((vec![Channel::Topic(1)], vec![]))
+    }
+  }
+
+  #[test]
+  fn remote_actor_answers_like_a_local_one() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr     = listener.local_addr().unwrap();
+    let server   = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      serve::<u32, u8>(stream, rc_cell!(Doubler))
+    });
+
+    let received   = RcCell::new(vec![]);
+    let mut router = Router::<u32, u8>::new();
+    let remote     = router.add_remote_actor(addr).unwrap();
+    router.add_actor(rc_cell!(Collector { received: received.clone() })).unwrap();
+    assert_eq!(remote, 0);
+
+    router.route(RcEnvelope::new(Envelope::new(1, Channel::General, Some(21), None)));
+    while router.process_next() {}
+    assert_eq!(*received.borrow(), vec![42]);
+
+    // Dropping the router closes the connection, which ends `serve()`.
+    drop(router);
+    server.join().unwrap().unwrap();
+  }
+}
@@ -2,18 +2,59 @@
 
 A `Router` owns the actors and orchestrates message passing.
 
+With the default `SequentialDispatcher`, the subscribers of a channel receive each message in
+the order they subscribed, which is the order they were added with `add_actor()`: an actor
+added earlier sees a message before an actor added later. (A replacement actor's new
+subscriptions, see `Router::replace_actor()`, come after the existing ones.) This is a stable
+guarantee that models may rely on.
+Forwarding targets (see `Router::forward_to()`) that don't subscribe to the channel come after
+its subscribers, and observers come last. A `ShuffledDispatcher` deliberately gives up the
+guarantee.
+
 */
 
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     cell::RefCell,
-    fmt::Debug
+    fmt::{self, Debug, Display},
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Index,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc
+    },
+    thread
+};
+
+use rand::{
+    rngs::SmallRng,
+    seq::index,
+    SeedableRng
 };
 use std::collections::VecDeque;
 use crate::{
     actor::{
+        Actor,
+        ActorError,
         ActorHandle,
-        RcActor
+        ActorResult,
+        AnyActor,
+        IntoRcActor,
+        MaybeSend,
+        RcActor,
+        RegistrationError
+    },
+    clock::{
+        BoxedClock,
+        SimulatedClock
+    },
+    debug::DebugSession,
+    dispatcher::{
+        Dispatcher,
+        SequentialDispatcher
     },
     message::{
         Channel,
@@ -21,214 +62,3419 @@ use crate::{
         Envelope,
         BoundedTopic
     },
+    rccell::{Cell, RcCell, Shared},
     timeline::{
         Timeline,
-        Event
+        Event,
+        ScheduleMode,
+        TieBreak,
+        Time,
+        TimelineId,
+        MAIN_TIMELINE
     },
 };
 
 pub const TIMELINE_HANDLE: ActorHandle = 0;
 
-/// It would be nice to just treat the timeline like any other actor. We could do that if we had a notion of
-pub struct Router<Message, Topic>
+/// The list of actors subscribed to a channel. Most channels have only a handful of
+/// subscribers, so with the `smallvec` feature we store up to four handles inline and
+/// avoid a heap allocation per channel.
+#[cfg(feature = "smallvec")]
+pub type Subscribers = smallvec::SmallVec<[ActorHandle; 4]>;
+#[cfg(not(feature = "smallvec"))]
+pub type Subscribers = Vec<ActorHandle>;
+
+/// A filter on envelopes sent on `Channel::Conditional`. With the `send` feature it must be
+/// `Send` and `Sync` so that the `Router` is `Send`.
+#[cfg(not(feature = "send"))]
+pub type PredicateFn<Message, Topic> = dyn Fn(&Envelope<Message, Topic>) -> bool;
+#[cfg(feature = "send")]
+pub type PredicateFn<Message, Topic> = dyn Fn(&Envelope<Message, Topic>) -> bool + Send + Sync;
+pub type Predicate<Message, Topic> = Box<PredicateFn<Message, Topic>>;
+
+/// Called with the queue length when the message queue rises above its high-water mark (see
+/// `Router::with_queue_alarm()`). With the `send` feature it must be `Send` and `Sync`.
+#[cfg(not(feature = "send"))]
+pub type QueueAlarmFn = dyn Fn(usize);
+#[cfg(feature = "send")]
+pub type QueueAlarmFn = dyn Fn(usize) + Send + Sync;
+
+/// How the actors added together with `Router::add_actor_group()` react to one another's errors.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GroupStrategy {
+    /// Each actor's errors only affect that actor. This is the default, and how actors added
+    /// with `add_actor()` behave.
+    #[default]
+    Independent,
+    /// If any actor in the group returns an error, fatal or recoverable, every actor in the
+    /// group stops receiving messages. Those not already stopped by a fatal error are first
+    /// sent a `Channel::Stop` message directly, which doesn't stop the simulation.
+    FailFast,
+}
+
+/// Invokes its callback once each time the message queue rises above `high_water`.
+#[derive(Clone)]
+struct QueueAlarm {
+    high_water: usize,
+    callback  : Shared<QueueAlarmFn>,
+    /// The queue is above `high_water` and the callback has been invoked.
+    raised    : bool,
+}
+
+/// A problem with the `Router`'s internal state, or a request the `Router` refused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouterError<Topic>
+    where Topic: BoundedTopic
+{
+    /// `handle` is subscribed to `channel` but does not refer to a live actor.
+    OrphanedSubscription {
+        channel: Channel<Topic>,
+        handle : ActorHandle,
+    },
+    /// `handle` does not refer to an actor.
+    NoSuchActor {
+        handle: ActorHandle,
+    },
+    /// The replacement for the actor with `handle` is not a newer version.
+    VersionNotNewer {
+        handle : ActorHandle,
+        current: u32,
+        offered: u32,
+    },
+    /// The replacement for the actor with `handle` refused to be registered.
+    RegistrationRejected {
+        handle: ActorHandle,
+        error : RegistrationError,
+    },
+}
+
+impl<Topic> Display for RouterError<Topic>
+    where Topic: BoundedTopic
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterError::OrphanedSubscription { channel, handle } => {
+                write!(f, "actor {} is subscribed to {:?} but is not a live actor", handle, channel)
+            }
+            RouterError::NoSuchActor { handle } => {
+                write!(f, "there is no actor {}", handle)
+            }
+            RouterError::VersionNotNewer { handle, current, offered } => {
+                write!(f, "actor {} is version {}, which replacement version {} does not supersede", handle, current, offered)
+            }
+            RouterError::RegistrationRejected { handle, error } => {
+                write!(f, "the replacement for actor {} refused registration: {}", handle, error.0)
+            }
+        }
+    }
+}
+
+impl<Topic> std::error::Error for RouterError<Topic> where Topic: BoundedTopic {}
+
+/// Tracks which `(channel, message)` pairs are waiting in the message queue so that
+/// duplicates can be dropped. The `Router` itself doesn't require `Message: Hash + Eq`, so
+/// we capture the hashing and comparison functions when deduplication is turned on.
+#[derive(Clone)]
+struct Dedup<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+    key   : fn(&Envelope<Message, Topic>) -> u64,
+    same  : fn(&Envelope<Message, Topic>, &Envelope<Message, Topic>) -> bool,
+    /// Map from key to the number of queued envelopes with that key
+    queued: HashMap<u64, usize>,
+}
+
+impl<Message, Topic> Dedup<Message, Topic>
+    where Message: Clone + Debug + Hash + Eq,
+          Topic  : BoundedTopic
+{
+    fn new() -> Self {
+        fn key<Message, Topic>(envelope: &Envelope<Message, Topic>) -> u64
+            where Message: Clone + Debug + Hash,
+                  Topic  : BoundedTopic
+        {
+            let mut hasher = DefaultHasher::new();
+            envelope.channel.hash(&mut hasher);
+            envelope.message.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        fn same<Message, Topic>(a: &Envelope<Message, Topic>, b: &Envelope<Message, Topic>) -> bool
+            where Message: Clone + Debug + Eq,
+                  Topic  : BoundedTopic
+        {
+            a.channel == b.channel && a.message == b.message
+        }
+
+        Dedup { key, same, queued: HashMap::new() }
+    }
+}
+
+/// Collects the responses to a `Channel::Aggregate` message until all `expected_responses`
+/// subscribers have answered, and then releases them as one batch. A subscriber that answers
+/// with nothing, or with an error, still counts as having answered.
+pub struct AggregateBarrier<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+    expected_responses: usize,
+    received_responses: usize,
+    batch             : Vec<RcEnvelope<Message, Topic>>,
+}
+
+impl<Message, Topic> AggregateBarrier<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+    pub fn new(expected_responses: usize) -> Self {
+        AggregateBarrier {
+            expected_responses,
+            received_responses: 0,
+            batch             : vec![],
+        }
+    }
+
+    /// Records one subscriber's answer.
+    pub fn respond<I>(&mut self, responses: I)
+        where I: IntoIterator<Item = RcEnvelope<Message, Topic>>
+    {
+        self.received_responses += 1;
+        self.batch.extend(responses);
+    }
+
+    /// Whether every expected subscriber has answered.
+    pub fn is_complete(&self) -> bool {
+        self.received_responses >= self.expected_responses
+    }
+
+    /// The responses, in the order the subscribers answered, once every subscriber has
+    /// answered. Until then, `None`.
+    pub fn into_batch(self) -> Option<Vec<RcEnvelope<Message, Topic>>> {
+        self.is_complete().then_some(self.batch)
+    }
+}
+
+/// Why `Router::run()` returned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// There were no messages or events left to process.
+    Exhausted,
+    /// A `Channel::Stop` message was routed.
+    StopRequested,
+    /// The next timeline event is after the time passed to `Router::run_until()`.
+    StopTimeReached,
+}
+
+/// What a call to `Router::step()` did.
+#[derive(Clone, Debug)]
+pub enum StepResult<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
+{
+    /// Routed this envelope from the message queue.
+    Message(RcEnvelope<Message, Topic>),
+    /// Routed this timeline event or heartbeat.
+    Event(RcEnvelope<Message, Topic>),
+    /// Nothing was ready to route, but deferred messages are counting down.
+    Waiting,
+    /// There were no messages or events left to process.
+    Exhausted,
+}
+
+/// The outcome of a call to `Router::run()`, available afterward from
+/// `Router::last_run_result()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunResult {
+    /// Why the run ended.
+    pub stop_reason: StopReason,
+    /// The number of routing steps the event loop took.
+    pub steps      : u64,
+    /// The simulated time when the run started.
+    pub start_time : Time,
+    /// The simulated time when the run ended.
+    pub end_time   : Time,
+}
+
+/// The state of a simulation at a point in simulated time, taken by `Router::snapshot_at()`.
+/// Holds only plain data, so it can be sent to another thread.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouterSnapshot {
+    /// The simulated time when the snapshot was taken.
+    pub time          : Time,
+    /// Each actor's name and `Actor::snapshot()`, indexed by handle.
+    pub actors        : Vec<(String, Option<String>)>,
+    /// The number of messages waiting in the message queue.
+    pub queue_len     : usize,
+    /// The number of events scheduled on the main timeline.
+    pub pending_events: usize,
+}
+
+/// Pauses and resumes a `Router`'s event loop from outside the simulation, e.g. from another
+/// thread or a signal handler. While the router is paused, `run()` spins until `resume()` is
+/// called. Obtained from `Router::pause_handle()`.
+#[derive(Clone, Debug)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+}
+
+/// Reports a suspicious but non-fatal condition.
+fn warn(message: &str) {
+    #[cfg(feature = "log")]
+    log::warn!("{}", message);
+    #[cfg(not(feature = "log"))]
+    eprintln!("Warning: {}", message);
+}
+
+/// It would be nice to just treat the timeline like any other actor. We could do that if we had a notion of
+pub struct Router<Message, Topic, D = SequentialDispatcher>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
 {
     /// List of `Actor`s participating in this `Router`. In this implementation, the
     /// `Router` owns the `Actor`s.
     actors       : Vec<RcActor<Message, Topic>>,
+    /// The actors as `Any`, by handle, for those whose concrete type is known (see
+    /// `IntoRcActor`). Used by `share()`.
+    actors_as_any: Vec<Option<AnyActor>>,
+    /// Read-only actors. They receive messages like any other actor, but anything they
+    /// emit is discarded.
+    observers    : Vec<RcActor<Message, Topic>>,
+    /// Map from channels to the indices into `observers` of the observers subscribed to them.
+    observer_subscriptions: HashMap<Channel<Topic>, Vec<usize>>,
     timeline     : Timeline<Message, Topic>,
+    /// Timelines added with `add_timeline()`, with their names. The timeline with ID `i` is
+    /// at index `i - 1`.
+    other_timelines: Vec<(String, Timeline<Message, Topic>)>,
     /// Map from channels to the actors subscribed to those channels.
     /// If the number of actors is known to be small, say, < 128, then you can
     /// use a bit mask instead of a `Vec<ActorHandle>`. You might also make this
     /// a HashSet or something to prevent double subscriptions.
-    subscriptions: RefCell<HashMap<Channel<Topic>, Vec<ActorHandle>>>,
+    subscriptions: RefCell<HashMap<Channel<Topic>, Subscribers>>,
+    /// Map from a sender and channel to the only actors that receive what that sender emits
+    /// on that channel. Checked before `subscriptions`.
+    directed_routes: HashMap<(ActorHandle, Channel<Topic>), Vec<ActorHandle>>,
+    /// Map from channels to the actors that receive everything sent on them in addition to
+    /// their subscribers. See `forward_to()`.
+    forwards: HashMap<Channel<Topic>, Vec<ActorHandle>>,
     /// A FIFO queue of messages ready for immediate processing
     message_queue: VecDeque<RcEnvelope<Message, Topic>>,
+    /// Messages sent on `Channel::Defer`, already addressed to their inner channel, with the
+    /// number of routing steps left before they are delivered.
+    deferred: VecDeque<(usize, RcEnvelope<Message, Topic>)>,
+    /// The largest number of messages that have been waiting in `message_queue` at once.
+    max_queue_depth_observed: usize,
+    /// The number of messages routed to a channel nobody subscribes to.
+    messages_dropped: u64,
+    /// The number of messages each actor has emitted in response to the messages it received.
+    messages_emitted: HashMap<ActorHandle, u64>,
+    /// The number of messages dispatched on each channel. See `channel_message_counts()`.
+    channel_message_counts: HashMap<Channel<Topic>, u64>,
+    /// If set, called when `message_queue` grows past its high-water mark.
+    queue_alarm: Option<QueueAlarm>,
+    /// If set, messages already waiting in `message_queue` are not enqueued again.
+    dedup: Option<Dedup<Message, Topic>>,
+    /// If set, the most `Channel::Unicast` messages that may wait in `message_queue` for any one actor.
+    per_actor_queue_limit: Option<usize>,
+    /// The number of `Channel::Unicast` messages waiting in `message_queue` for each actor.
+    unicast_queue_depths: HashMap<ActorHandle, usize>,
+    /// Actors that keep their subscriptions but are skipped during delivery.
+    paused: HashSet<ActorHandle>,
+    /// Actors that returned `ActorError::Fatal` and no longer receive messages.
+    stopped: HashSet<ActorHandle>,
+    /// Actors removed with `remove_actor()`. Their handles are not reused.
+    removed: HashSet<ActorHandle>,
+    /// The number of actors, in order of handle, whose `Actor::setup()` has been called.
+    actors_set_up: usize,
+    /// The members of each group added with `GroupStrategy::FailFast`.
+    fail_fast_groups: Vec<Vec<ActorHandle>>,
+    /// Chooses the recipients of `Channel::Gossip` messages.
+    gossip_rng: SmallRng,
+    /// For each `Channel::RoundRobin`, the position among its subscribers of the next recipient.
+    round_robin_state: HashMap<Channel<Topic>, usize>,
+    /// Snapshots requested with `snapshot_at()` that are not yet due, with where to send them.
+    pending_snapshots: Vec<(Time, Sender<RouterSnapshot>)>,
+    /// The whole simulation is paused. Shared with every `PauseHandle`.
+    simulation_paused: PauseHandle,
+    /// `Channel::SimulationStarted` has been routed.
+    simulation_started: bool,
     /// An early exit has been triggered
     stop_requested: bool,
     /// Debug session has been triggered.
     debug_requested: bool,
+    /// `run()` is currently executing.
+    is_running: bool,
+    /// The outcome of the last call to `run()`. See `last_run_result()`.
+    run_result: Option<RunResult>,
+    /// Fans each routed message out to the channel's subscribers.
+    dispatcher: D,
+    /// Paces the processing of timeline events.
+    clock: BoxedClock,
+    /// The heartbeat interval and the time of the next heartbeat, if heartbeats are enabled.
+    heartbeat: Option<(Time, Time)>,
+    /// The ID given to the next envelope routed without one.
+    next_message_id: u64,
+    /// The most events one actor may schedule in response to a single envelope, if limited.
+    event_storm_limit: Option<usize>,
+    /// The filters for `Channel::Conditional`, by predicate ID. Shared so that
+    /// `clone_for_testing()` can copy them.
+    predicates: HashMap<u32, Shared<PredicateFn<Message, Topic>>>,
+    /// Whether `causal_graph` is recorded.
+    causal_tracking: bool,
+    /// The IDs of the messages each message caused, keyed by the ID of the cause.
+    causal_graph: HashMap<u64, Vec<u64>>,
 }
 
-impl<Message, Topic> Default for Router<Message, Topic>
+impl<Message, Topic, D> Default for Router<Message, Topic, D>
     where Message: Clone + Debug,
-          Topic  : BoundedTopic
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic> + Default
 {
     fn default() -> Self {
+        Router::with_dispatcher(D::default())
+    }
+}
+
+impl<Message, Topic, D> Router<Message, Topic, D>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic> + Default
+{
+    pub fn new() -> Self {
+      Router::default()
+    }
+}
+
+impl<Message, Topic, D> Router<Message, Topic, D>
+    where Message: Clone + Debug + Hash + Eq,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
+{
+    /// When `enabled`, a message is not enqueued if a message with the same channel and
+    /// payload is already waiting in the message queue. Sender and time are ignored. This
+    /// avoids redundant work when, e.g., several actors request the same report in one step.
+    pub fn with_dedup(mut self, enabled: bool) -> Self {
+        self.dedup = enabled.then(Dedup::new);
+        self
+    }
+}
+
+#[cfg(feature = "remote")]
+impl<Message, Topic, D> Router<Message, Topic, D>
+    where Message: Clone + Debug + serde::Serialize + serde::de::DeserializeOwned + 'static,
+          Topic  : BoundedTopic + serde::Serialize + serde::de::DeserializeOwned + 'static,
+          D      : Dispatcher<Message, Topic>
+{
+    /// Connects to an actor served by `remote::serve()` at `addr` and adds a `RemoteActor`
+    /// that forwards its messages there. Returns the handle the remote actor is registered with.
+    pub fn add_remote_actor(&mut self, addr: std::net::SocketAddr) -> std::io::Result<ActorHandle> {
+        let actor = crate::remote::RemoteActor::connect(addr)?;
+        self.add_actor(crate::rc_cell!(actor)).map_err(std::io::Error::other)
+    }
+}
+
+impl<Message, Topic, D> Router<Message, Topic, D>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
+{
+    /// Creates a `Router` that uses `dispatcher` to fan messages out to subscribers.
+    pub fn with_dispatcher(dispatcher: D) -> Self {
         Router{
             actors         : vec![],
+            actors_as_any  : vec![],
+            observers      : vec![],
+            observer_subscriptions: HashMap::new(),
             timeline       : Timeline::default(),
+            other_timelines: vec![],
             subscriptions  : RefCell::new(HashMap::default()),
+            directed_routes: HashMap::new(),
+            forwards       : HashMap::new(),
             message_queue  : VecDeque::new(),
+            deferred       : VecDeque::new(),
+            max_queue_depth_observed: 0,
+            messages_dropped: 0,
+            messages_emitted: HashMap::new(),
+            channel_message_counts: HashMap::new(),
+            queue_alarm    : None,
+            dedup          : None,
+            per_actor_queue_limit: None,
+            unicast_queue_depths: HashMap::new(),
+            paused         : HashSet::new(),
+            stopped        : HashSet::new(),
+            removed        : HashSet::new(),
+            actors_set_up  : 0,
+            fail_fast_groups: vec![],
+            gossip_rng     : SmallRng::seed_from_u64(0),
+            round_robin_state: HashMap::new(),
+            pending_snapshots: vec![],
+            simulation_paused: PauseHandle { paused: Arc::new(AtomicBool::new(false)) },
+            simulation_started: false,
             stop_requested : false,
             debug_requested: false,
+            is_running     : false,
+            run_result     : None,
+            dispatcher,
+            clock          : Box::new(SimulatedClock::default()),
+            heartbeat      : None,
+            next_message_id: 0,
+            event_storm_limit: None,
+            predicates     : HashMap::new(),
+            causal_tracking: false,
+            causal_graph   : HashMap::new(),
         }
     }
-}
 
-impl<Message, Topic> Router<Message, Topic>
-    where Message: Clone + Debug,
-          Topic  : BoundedTopic
-{
-    pub fn new() -> Self {
-      Router::default()
+    /// Replaces the clock that paces timeline events. The default `SimulatedClock` runs at
+    /// maximum speed; a `WallClock` runs the simulation in real time.
+    pub fn with_clock(mut self, clock: BoxedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Delivers a `Channel::Heartbeat` message to every actor, subscribed or not, at each
+    /// multiple of `interval` of simulated time. A heartbeat is sent whenever the timeline is
+    /// about to advance past a multiple of `interval`, so heartbeats only occur while there
+    /// are events left to process and never keep the simulation alive on their own. The
+    /// heartbeat envelope's `time` is the heartbeat's time.
+    pub fn with_heartbeat(mut self, interval: Time) -> Self {
+        assert!(interval.0 > 0.0, "heartbeat interval must be positive");
+        self.heartbeat = Some((interval, self.timeline.now() + interval));
+        self
+    }
+
+    /// Invokes `callback` with the queue length when the message queue grows past
+    /// `high_water`, e.g. to log a warning about a runaway simulation. The callback is invoked
+    /// once; it is invoked again only after the queue has drained below `high_water`.
+    pub fn with_queue_alarm(mut self, high_water: usize, callback: Box<QueueAlarmFn>) -> Self {
+        self.queue_alarm = Some(QueueAlarm { high_water, callback: Shared::from(callback), raised: false });
+        self
+    }
+
+    /// Applies backpressure to `Channel::Unicast`: once `limit` unicast messages are waiting for
+    /// an actor, a sender of another one is blocked until the actor has received one of them.
+    /// The `Router` is single threaded, so blocking the sender means routing the queued
+    /// messages, in order, until there's room, before the sender's remaining responses are
+    /// enqueued. Time doesn't advance while the sender is blocked.
+    pub fn with_per_actor_queue_limit(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "the per-actor queue limit must be positive");
+        self.per_actor_queue_limit = Some(limit);
+        self
+    }
+
+    /// Limits how many events an actor may schedule in response to a single envelope. Beyond
+    /// `limit`, a `Channel::Warning` is raised and the actor's remaining `ScheduleEvent`
+    /// messages for that step are dropped. Guards against runaway actors.
+    pub fn with_event_storm_limit(mut self, limit: usize) -> Self {
+        self.event_storm_limit = Some(limit);
+        self
+    }
+
+    /// When `enabled`, records which message caused which in `causal_graph()` and in each
+    /// envelope's `correlation_id`: each actor's responses are children of the message it
+    /// received, and a fired timeline event is a child of the message that scheduled it. To
+    /// make the graph well defined, a child gets its message ID as soon as it is produced rather
+    /// than when it is routed, and a response that is a copy of its parent gets an ID of its own.
+    pub fn with_causal_tracking(mut self, enabled: bool) -> Self {
+        self.causal_tracking = enabled;
+        self
+    }
+
+    /// Seeds the random choice of the recipients of `Channel::Gossip` messages, which is
+    /// otherwise seeded with 0. Either way, a simulation gossips the same way every time it runs.
+    pub fn with_gossip_seed(mut self, seed: u64) -> Self {
+        self.gossip_rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Orders events scheduled for the same time according to `strategy` instead of the
+    /// default `TieBreak::OldestFirst`. Must be called before any events are scheduled.
+    pub fn with_tie_break(mut self, strategy: TieBreak) -> Self {
+        assert!(self.timeline.next_time().is_none(), "the tie break must be set before scheduling events");
+        self.timeline = Timeline::new_with_tie_break(strategy);
+        self
+    }
+
+    /// Creates an independent copy of this `Router`: every actor is deep-cloned via
+    /// `Actor::clone_actor()`, and the timeline and message queue are copied. Envelopes
+    /// are immutable, so the copies share them. This lets a test apply two different
+    /// interventions to the same simulation state and compare the outcomes. The copy always
+    /// uses a `SimulatedClock`.
+    ///
+    /// Panics if any actor does not support cloning.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn clone_for_testing(&self) -> Self
+        where D: Clone
+    {
+        fn clone_all<Message, Topic>(actors: &[RcActor<Message, Topic>]) -> Vec<RcActor<Message, Topic>>
+            where Message: Clone + Debug,
+                  Topic  : BoundedTopic
+        {
+            actors
+                .iter()
+                .map(|actor| {
+                    let actor = actor.borrow();
+                    actor.clone_actor()
+                         .unwrap_or_else(|| panic!("actor {} does not implement `Actor::clone_actor()`", actor.name()))
+                })
+                .collect()
+        }
+
+        let mut clone = Router::with_dispatcher(self.dispatcher.clone());
+
+        clone.actors                   = clone_all(&self.actors);
+        // `Actor::clone_actor()` returns an `RcActor`, so the clones' types are unknown.
+        clone.actors_as_any            = vec![None; self.actors.len()];
+        clone.observers                = clone_all(&self.observers);
+        clone.observer_subscriptions   = self.observer_subscriptions.clone();
+        clone.timeline                 = self.timeline.clone();
+        clone.other_timelines          = self.other_timelines.clone();
+        clone.subscriptions            = RefCell::new(self.subscriptions.borrow().clone());
+        clone.directed_routes          = self.directed_routes.clone();
+        clone.forwards                 = self.forwards.clone();
+        clone.message_queue            = self.message_queue.clone();
+        clone.deferred                 = self.deferred.clone();
+        clone.max_queue_depth_observed = self.max_queue_depth_observed;
+        clone.messages_dropped         = self.messages_dropped;
+        clone.messages_emitted         = self.messages_emitted.clone();
+        clone.channel_message_counts   = self.channel_message_counts.clone();
+        clone.queue_alarm              = self.queue_alarm.clone();
+        clone.dedup                    = self.dedup.clone();
+        clone.per_actor_queue_limit    = self.per_actor_queue_limit;
+        clone.unicast_queue_depths     = self.unicast_queue_depths.clone();
+        clone.paused                   = self.paused.clone();
+        clone.stopped                  = self.stopped.clone();
+        clone.removed                  = self.removed.clone();
+        clone.actors_set_up            = self.actors_set_up;
+        clone.fail_fast_groups         = self.fail_fast_groups.clone();
+        clone.gossip_rng               = self.gossip_rng.clone();
+        clone.round_robin_state        = self.round_robin_state.clone();
+        // The clone gets its own flag, so pausing one router doesn't pause the other.
+        clone.simulation_paused        = PauseHandle {
+            paused: Arc::new(AtomicBool::new(self.simulation_paused.is_paused()))
+        };
+        clone.simulation_started       = self.simulation_started;
+        clone.stop_requested           = self.stop_requested;
+        clone.debug_requested          = self.debug_requested;
+        clone.heartbeat                = self.heartbeat;
+        clone.next_message_id          = self.next_message_id;
+        clone.event_storm_limit        = self.event_storm_limit;
+        clone.predicates               = self.predicates.clone();
+        clone.causal_tracking          = self.causal_tracking;
+        clone.causal_graph             = self.causal_graph.clone();
+
+        clone
     }
 
     /// Adds the actor to the router. The `Router` owns the actor, so we take a `BxActor`.
-    /// (We could allow actors in multiple routers, but we don't.)
-    pub fn add_actor(&mut self, actor: RcActor<Message, Topic>) {
+    /// (We could allow actors in multiple routers, but we don't.) Subscribers receive messages
+    /// in the order they were added (see the module documentation). Returns the actor's handle, or
+    /// the actor's reason for refusing registration, in which case the actor is not added.
+    pub fn add_actor<A>(&mut self, actor: A) -> Result<ActorHandle, RegistrationError>
+        where A: IntoRcActor<Message, Topic>
+    {
+        let (actor, actor_as_any) = actor.into_rc_actor();
         let actor_handle = self.actors.len() as ActorHandle;
         self.actors.push(actor.clone());
+        self.actors_as_any.push(actor_as_any);
 
         // Inform the actor of its registration with the router.
-        let mut actor_mut = actor.borrow_mut();
-        let (new_subscriptions, new_messages) = actor_mut.register(actor_handle);
+        let registration = actor.borrow_mut().register(actor_handle);
+        let (new_subscriptions, new_messages) = match registration {
+            Ok(registration) => registration,
+            Err(error) => {
+                self.actors.pop();
+                self.actors_as_any.pop();
+                return Err(error);
+            }
+        };
 
         // Act on the actor's subscriptions and messages
         let mut subscriptions = self.subscriptions.borrow_mut();
-        for channel in new_subscriptions {
-            let subscribers = subscriptions.entry(channel).or_insert_with(Vec::new);
+        for channel in new_subscriptions.into_iter().flat_map(Channel::into_subscriptions) {
+            let subscribers = subscriptions.entry(channel).or_default();
             subscribers.push(actor_handle);
         }
+        drop(subscriptions);
         // Enqueue the actor's initial outgoing messages
-        self.message_queue.extend(new_messages);
+        self.enqueue(new_messages);
+
+        Ok(actor_handle)
     }
 
-    /// Begins the event loop
-    pub fn run(&mut self) {
-        loop {
-            if self.stop_requested {
-                eprintln!("Stopping early.");
-                return;
-            }
+    /// Adds the actors, in order, as a group whose members react to one another's errors
+    /// according to `strategy`, and returns their handles. If an actor refuses registration,
+    /// the actors before it stay added, but not as a group.
+    pub fn add_actor_group(
+        &mut self,
+        actors  : Vec<RcActor<Message, Topic>>,
+        strategy: GroupStrategy
+    ) -> Result<Vec<ActorHandle>, RegistrationError> {
+        let handles = actors
+            .into_iter()
+            .map(|actor| self.add_actor(actor))
+            .collect::<Result<Vec<_>, _>>()?;
+        if strategy == GroupStrategy::FailFast {
+            self.fail_fast_groups.push(handles.clone());
+        }
+        Ok(handles)
+    }
 
-            // Message queue processed before timeline.
-            if let Some(envelope) = self.message_queue.pop_front() {
-                self.route(envelope);
-                // We continue, because an actor might have placed something in the
-                // message queue that needs processing before we process the timeline.
-                continue;
-            }
+    /// Adds `count` actors made by `f`, which is passed each actor's index in the pool, and
+    /// returns their handles, e.g. for one identical worker per region. Like
+    /// `add_actor_group()`, if an actor refuses registration, the actors before it stay added.
+    pub fn with_actor_pool<F>(&mut self, f: F, count: usize) -> Result<Vec<ActorHandle>, RegistrationError>
+        where F: Fn(usize) -> RcActor<Message, Topic>
+    {
+        (0..count).map(|index| self.add_actor(f(index))).collect()
+    }
 
-            if let Some(event) = self.timeline.pop() {
-                let Event{ envelope: event_envelope, time} = event;
-                let Envelope{from, ..} = event_envelope.as_ref();
+    /// Swaps in a newer version of the actor with `handle`, e.g. to patch a running simulation.
+    /// `new_actor` keeps the old actor's handle and subscriptions and is registered with the
+    /// handle, which may add subscriptions and send initial messages. If the old actor was
+    /// stopped by a fatal error, the new one is not. The old actor is dropped by the `Router`. If
+    /// `new_actor` refuses registration, the old actor stays in place.
+    pub fn replace_actor<A>(&mut self, handle: ActorHandle, new_actor: A) -> Result<(), RouterError<Topic>>
+        where A: IntoRcActor<Message, Topic>
+    {
+        let (new_actor, new_actor_as_any) = new_actor.into_rc_actor();
+        let Some(old_actor) = self.actors.get(handle as usize).filter(|_| !self.removed.contains(&handle)) else {
+            return Err(RouterError::NoSuchActor { handle });
+        };
+        let current = old_actor.borrow().version();
+        let offered = new_actor.borrow().version();
+        if offered <= current {
+            return Err(RouterError::VersionNotNewer { handle, current, offered });
+        }
 
-                let envelope = Envelope{
-                    from   : *from,
-                    channel: Channel::TimelineEvent,
-                    message: event_envelope.message.clone(),
-                    time   : Some(time)
-                };
-                self.route(RcEnvelope::new(envelope));
-            } else {
-                // All messages & events are exhausted.
-                break;
+        let (new_subscriptions, new_messages) = new_actor
+            .borrow_mut()
+            .register(handle)
+            .map_err(|error| RouterError::RegistrationRejected { handle, error })?;
+        self.actors[handle as usize]        = new_actor;
+        self.actors_as_any[handle as usize] = new_actor_as_any;
+        self.stopped.remove(&handle);
+
+        // The old subscriptions are keyed by handle, so they already belong to the new actor.
+        let mut subscriptions = self.subscriptions.borrow_mut();
+        for channel in new_subscriptions.into_iter().flat_map(Channel::into_subscriptions) {
+            let subscribers = subscriptions.entry(channel).or_default();
+            if !subscribers.contains(&handle) {
+                subscribers.push(handle);
             }
         }
+        drop(subscriptions);
+        self.enqueue(new_messages);
+
+        Ok(())
     }
 
-    /// Handles a single message in the message queue.
-    /// (This method could be public.)
-    pub fn route(&mut self, envelope: RcEnvelope<Message, Topic>) {
-        // Process system messages
-        if self.act_on_system_message(envelope.clone()) {
-            // The `act_on_system_message()` function returns true if we should stop routing.
-            return;
+    /// Removes the actor with `handle` from the simulation. The actor's `Actor::on_stop()` is
+    /// called, then every remaining actor is sent a `Channel::Tombstone(handle)` message,
+    /// which is routed right away, so that actors that refer to the removed one can forget it.
+    /// Finally the actor's subscriptions, routes, and group membership are dropped. The other
+    /// actors keep their handles, and `handle` is not reused.
+    pub fn remove_actor(&mut self, handle: ActorHandle) -> Result<(), RouterError<Topic>> {
+        if handle as usize >= self.actors.len() || self.removed.contains(&handle) {
+            return Err(RouterError::NoSuchActor { handle });
         }
 
-        let mut subscriptions = self.subscriptions.borrow_mut();
-        let subscribers = subscriptions.entry(envelope.channel).or_insert_with(Vec::new);
+        self.actors[handle as usize].borrow_mut().on_stop();
+        self.removed.insert(handle);
+        // Stopped actors receive nothing, including their own tombstone.
+        self.stopped.insert(handle);
+        self.route(RcEnvelope::new(
+            Envelope::new(TIMELINE_HANDLE, Channel::Tombstone(handle), None, Some(self.timeline.now()))
+        ));
+
+        for subscribers in self.subscriptions.get_mut().values_mut() {
+            subscribers.retain(|subscriber| *subscriber != handle);
+        }
+        self.directed_routes.retain(|(from, _), _| *from != handle);
+        for targets in self.directed_routes.values_mut().chain(self.forwards.values_mut()) {
+            targets.retain(|target| *target != handle);
+        }
+        for group in &mut self.fail_fast_groups {
+            group.retain(|member| *member != handle);
+        }
+        self.paused.remove(&handle);
 
-        for handle in subscribers {
-            // let subscriber: &RcActor<Message, Topic> = self.actors.get(*handle as usize).unwrap();
-            let subscriber: &RcActor<Message, Topic> = &self.actors[*handle as usize];
+        Ok(())
+    }
 
-            let mut receiver = subscriber.borrow_mut();
-            let response     = receiver.receive_message(envelope.clone());
+    /// Adds a read-only observer, e.g. for logging or metrics. An observer receives the messages
+    /// on the channels it subscribes to, but cannot affect the simulation: any messages it emits,
+    /// including on registration, are discarded with a warning. Observers are stored separately
+    /// from regular actors and are registered with the handle `ActorHandle::MAX - i` for the
+    /// `i`th observer, so their handles never collide with those of regular actors. Returns the
+    /// observer's handle, or its reason for refusing registration, in which case it is not added.
+    pub fn add_observer(&mut self, observer: RcActor<Message, Topic>) -> Result<ActorHandle, RegistrationError> {
+        let index  = self.observers.len();
+        let handle = ActorHandle::MAX - index as ActorHandle;
 
-            self.message_queue.extend(response);
+        let (new_subscriptions, new_messages) = observer.borrow_mut().register(handle)?;
+        self.observers.push(observer.clone());
+        for channel in new_subscriptions.into_iter().flat_map(Channel::into_subscriptions) {
+            self.observer_subscriptions.entry(channel).or_default().push(index);
         }
+        if let Some(warning) = Self::discard_observer_messages(&observer, new_messages) {
+            self.raise_warning(warning);
+        }
+
+        Ok(handle)
     }
 
-    /// Factored out from `route()`, returns true if `route()` should return without routing.
-    fn act_on_system_message(&mut self, envelope: RcEnvelope<Message, Topic>) -> bool {
-        // Check for system- or timeline-specific messages
-        match envelope.as_ref() {
-            Envelope { channel: Channel::ScheduleEvent, time: Some(time), .. } => {
-                // A real implementation would have more elaborate error handling.
-                assert!(*time >= self.timeline.now());
-
-                self.timeline.push(
-                    Event {
-                        time: *time,
-                        envelope: envelope.clone(),
-                    }
-                );
-                // We do not return, because other actors might wish to act on timeline messages
-                false
-            }
+    /// Returns the warning to raise if the observer emitted any messages.
+    fn discard_observer_messages(
+        observer: &RcActor<Message, Topic>,
+        messages: Vec<RcEnvelope<Message, Topic>>
+    ) -> Option<Cow<'static, str>> {
+        if messages.is_empty() {
+            return None;
+        }
+        Some(Cow::Owned(format!(
+            "observer {} emitted {} message(s), which were discarded",
+            observer.borrow().name(),
+            messages.len()
+        )))
+    }
 
-            Envelope { channel: Channel::Time, time: None, .. } => {
-                // If the time is empty, it's a request for the current time.
-                let new_envelope = Envelope {
-                    from   : ActorHandle::default(),
-                    channel: Channel::Time,
-                    message: None,
-                    time   : Some(self.timeline.now())
-                };
-                #[cfg(feature = "print_messages")]
-                println!("ROUTER/TIMELINE: {:?}", new_envelope);
-                self.message_queue.push_back(RcEnvelope::new(new_envelope));
-                false
+    /// Reports a suspicious but non-fatal condition, both through `warn()` and by enqueueing a
+    /// `Channel::Warning` message so that actors can collect warnings.
+    fn raise_warning(&mut self, text: Cow<'static, str>) {
+        warn(&text);
+        self.enqueue([RcEnvelope::new(
+            Envelope::new(TIMELINE_HANDLE, Channel::Warning(text), None, Some(self.timeline.now()))
+        )]);
+    }
+
+    /// Whether `handle` is the handle of an actor that exists and hasn't been removed.
+    fn is_live(&self, handle: ActorHandle) -> bool {
+        (handle as usize) < self.actors.len() && !self.removed.contains(&handle)
+    }
+
+    /// The `targets` the envelope names that are live actors. Raises a warning for the others.
+    fn live_recipients(&mut self, envelope: &Envelope<Message, Topic>, targets: &[ActorHandle]) -> Vec<ActorHandle> {
+        let (live, dead): (Vec<ActorHandle>, Vec<ActorHandle>) = targets.iter().partition(|&&target| self.is_live(target));
+        for target in dead {
+            self.raise_warning(Cow::Owned(format!(
+                "actor {} sent a message to actor {}, which doesn't exist or was removed; dropping it for that recipient",
+                envelope.from,
+                target
+            )));
+        }
+        live
+    }
+
+    /// Registers the filter for envelopes sent on `Channel::Conditional` with this
+    /// `predicate_id`, replacing any filter already registered under that ID.
+    pub fn register_predicate(&mut self, id: u32, predicate: Predicate<Message, Topic>) {
+        self.predicates.insert(id, Shared::from(predicate));
+    }
+
+    /// Routes whatever `from` emits on `channel` to `to` alone, bypassing the channel's
+    /// subscribers. Adding several routes for the same `from` and `channel` delivers to each
+    /// of their `to`s, in the order the routes were added.
+    pub fn add_directed_route(&mut self, from: ActorHandle, to: ActorHandle, channel: Channel<Topic>) {
+        self.directed_routes.entry((from, channel)).or_default().push(to);
+    }
+
+    /// Subscribes the actor with `handle` to `channel`, e.g. from `Actor::setup()`, as though it
+    /// had subscribed when it was registered.
+    pub fn subscribe(&mut self, handle: ActorHandle, channel: Channel<Topic>) {
+        let mut subscriptions = self.subscriptions.borrow_mut();
+        for channel in channel.into_subscriptions() {
+            let subscribers = subscriptions.entry(channel).or_default();
+            if !subscribers.contains(&handle) {
+                subscribers.push(handle);
             }
+        }
+    }
 
-            Envelope { channel: Channel::Stop, .. } => {
-                self.stop_requested = true;
-                // ToDo: Should we return without routing anything else?
-                true
+    /// Delivers every message sent on any of `channels` to `handle` as well, whether or not
+    /// `handle` subscribes to them, e.g. to set up a proxy or a load balancer. Unlike a
+    /// directed route, forwarding doesn't take the message away from the channel's subscribers.
+    pub fn forward_to(&mut self, handle: ActorHandle, channels: Vec<Channel<Topic>>) {
+        for channel in channels.into_iter().flat_map(Channel::into_subscriptions) {
+            let targets = self.forwards.entry(channel).or_default();
+            if !targets.contains(&handle) {
+                targets.push(handle);
             }
+        }
+    }
 
-            Envelope { channel: Channel::Debug, .. } => {
-                self.debug_requested = true;
-                // ToDo: Should we return without routing anything else?
-                true
+    /// Checks that every handle in the subscription map refers to a live actor, i.e. one that
+    /// exists and hasn't been removed, and reports those that don't. Called at the start of
+    /// `run()` in debug builds.
+    pub fn validate_subscriptions(&self) -> Vec<RouterError<Topic>> {
+        let mut errors = vec![];
+
+        for (channel, subscribers) in self.subscriptions.borrow().iter() {
+            for &handle in subscribers.iter() {
+                if !self.is_live(handle) {
+                    errors.push(RouterError::OrphanedSubscription { channel: channel.clone(), handle });
+                }
             }
+        }
 
-            _ => {
-                // Not a system message
-                false
+        errors
+    }
+
+    /// The largest number of messages that were waiting in the message queue at any one time.
+    /// A large value usually points to a pathological message cascade.
+    pub fn max_queue_depth(&self) -> usize {
+        self.max_queue_depth_observed
+    }
+
+    /// The number of messages that were routed to a channel with no subscribers, neither actors
+    /// nor observers. A nonzero value often points to a missing or misspelled subscription.
+    pub fn messages_dropped(&self) -> u64 {
+        self.messages_dropped
+    }
+
+    /// The number of messages dispatched on each channel, keyed by the channel's `Debug` format,
+    /// e.g. `"Topic(1)"`. A message counts once however many actors receive it, including none.
+    /// Messages the `Router` consumes itself aren't dispatched, and a `Channel::RequestReply` or
+    /// `Channel::Conditional` message counts toward the channel it is delivered on. Points to the
+    /// overloaded channels when the message queue grows unexpectedly.
+    pub fn channel_message_counts(&self) -> HashMap<String, u64> {
+        self.channel_message_counts
+            .iter()
+            .map(|(channel, &count)| (format!("{:?}", channel), count))
+            .collect()
+    }
+
+    /// The actor that has emitted the most messages in response to the messages it received,
+    /// or `None` if no actor has emitted any. Ties go to the lowest handle. The first suspect
+    /// when the message queue grows unexpectedly.
+    pub fn top_emitter(&self) -> Option<ActorHandle> {
+        self.messages_emitted
+            .iter()
+            .max_by_key(|&(&handle, &count)| (count, Reverse(handle)))
+            .map(|(&handle, _)| handle)
+    }
+
+    /// Appends messages to the back of the message queue, keeping track of the queue's high-water mark.
+    fn enqueue<I>(&mut self, envelopes: I)
+        where I: IntoIterator<Item = RcEnvelope<Message, Topic>>
+    {
+        for envelope in envelopes {
+            self.wait_for_room(&envelope);
+
+            if let Some(dedup) = &mut self.dedup {
+                let key   = (dedup.key)(&envelope);
+                let count = dedup.queued.entry(key).or_default();
+                // Only on a key match do we need to look for an actual duplicate.
+                if *count > 0 && self.message_queue.iter().any(|queued| (dedup.same)(queued, &envelope)) {
+                    continue;
+                }
+                *count += 1;
             }
-        } // end match
+            // A message for a nonexistent actor won't be delivered, so it doesn't fill a queue.
+            if let Channel::Unicast(target) = envelope.channel
+                && self.is_live(target)
+            {
+                *self.unicast_queue_depths.entry(target).or_default() += 1;
+            }
+            self.message_queue.push_back(envelope);
+        }
+        self.max_queue_depth_observed = self.max_queue_depth_observed.max(self.message_queue.len());
+
+        if let Some(alarm) = &mut self.queue_alarm
+            && !alarm.raised
+            && self.message_queue.len() > alarm.high_water
+        {
+            alarm.raised = true;
+            (alarm.callback)(self.message_queue.len());
+        }
     }
 
-    /// Processes system messages without broadcasting to non system actors. For non system messages,
-    /// Routes the envelope, but collects the responses in a vector and returns them instead of
-    /// putting them in a queue.
-    ///
-    /// This is useful for testing / debugging.
-    pub fn silent_route(&mut self, envelope: RcEnvelope<Message, Topic>) -> Vec<RcEnvelope<Message, Topic>> {
-        // Process system messages
-        if self.act_on_system_message(envelope.clone()) {
-            // The `act_on_system_message()` function returns true if we should stop routing.
-            return vec![];
+    /// Blocks the sender of a `Channel::Unicast` message while its recipient's queue is full
+    /// by routing queued messages. See `with_per_actor_queue_limit()`.
+    fn wait_for_room(&mut self, envelope: &Envelope<Message, Topic>) {
+        let (Some(limit), Channel::Unicast(target)) = (self.per_actor_queue_limit, &envelope.channel) else {
+            return;
+        };
+        if !self.is_live(*target) {
+            return;
         }
+        while self.unicast_queue_depths.get(target).is_some_and(|&depth| depth >= limit)
+            && let Some(queued) = self.dequeue()
+        {
+            self.route(queued);
+        }
+    }
 
-        let mut subscriptions = self.subscriptions.borrow_mut();
-        let subscribers       = subscriptions.entry(envelope.channel).or_insert_with(Vec::new);
-        let mut responses     = vec![];
+    /// Removes the message at the front of the message queue.
+    fn dequeue(&mut self) -> Option<RcEnvelope<Message, Topic>> {
+        let envelope = self.message_queue.pop_front()?;
 
-        for handle in subscribers {
-            let subscriber: &RcActor<Message, Topic> = self.actors.get(*handle as usize).unwrap();
+        if let Channel::Unicast(target) = envelope.channel
+            && let Some(depth) = self.unicast_queue_depths.get_mut(&target)
+        {
+            *depth -= 1;
+            if *depth == 0 {
+                self.unicast_queue_depths.remove(&target);
+            }
+        }
 
-            let mut receiver = subscriber.borrow_mut();
-            let response     = receiver.receive_message(envelope.clone());
-            // Instead of adding the responses the message queue, we accumulate and return them.
-            responses.extend(response);
+        if let Some(alarm) = &mut self.queue_alarm
+            && self.message_queue.len() < alarm.high_water
+        {
+            alarm.raised = false;
         }
 
-        responses
+        if let Some(dedup) = &mut self.dedup {
+            let key = (dedup.key)(&envelope);
+            if let Some(count) = dedup.queued.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    dedup.queued.remove(&key);
+                }
+            }
+        }
+
+        Some(envelope)
+    }
+
+    /// Pauses the actor with the given handle. A paused actor keeps its subscriptions but is
+    /// not delivered any messages until it is resumed.
+    pub fn pause_actor(&mut self, handle: ActorHandle) {
+        self.paused.insert(handle);
+    }
+
+    /// Resumes delivery of messages to a paused actor. Messages routed while the actor was
+    /// paused are not replayed.
+    pub fn resume_actor(&mut self, handle: ActorHandle) {
+        self.paused.remove(&handle);
+    }
+
+    /// Schedules an event directly on the envelope's timeline without routing a
+    /// `Channel::ScheduleEvent` message. Useful for tests and for seeding the timeline during
+    /// initialization.
+    ///
+    /// Panics if the envelope's timeline does not exist.
+    pub fn push_timeline_event(&mut self, time: Time, envelope: RcEnvelope<Message, Topic>) {
+        self.timeline_mut(envelope.timeline_id)
+            .expect("no timeline with the envelope's timeline ID")
+            .push(Event::new(time, envelope));
+    }
+
+    /// Removes the scheduled events, on every timeline, for which `predicate` returns `true`,
+    /// and returns how many it removed. No timeline's clock changes. See `Timeline::cancel()`.
+    pub fn cancel_events<F>(&mut self, predicate: F) -> usize
+        where F: Fn(&Event<Message, Topic>) -> bool
+    {
+        let mut cancelled = self.timeline.cancel(&predicate);
+        for (_, timeline) in &mut self.other_timelines {
+            cancelled += timeline.cancel(&predicate);
+        }
+        cancelled
+    }
+
+    /// Adds a timeline with its own clock, e.g. for a calendar that runs independently of
+    /// simulated time, and returns its ID. Schedule events on it with
+    /// `Envelope::on_timeline()`. Events from all timelines are processed in order of time, so
+    /// the timelines should measure time in the same units. Only events on the main timeline
+    /// are paced by the `Router`'s clock or preceded by heartbeats.
+    pub fn add_timeline(&mut self, name: &str) -> TimelineId {
+        self.other_timelines.push((name.to_string(), Timeline::default()));
+        self.other_timelines.len() as TimelineId
     }
 
+    /// The current time on the given timeline, or `None` if there is no such timeline.
+    pub fn now_on(&self, timeline_id: TimelineId) -> Option<Time> {
+        self.timeline_ref(timeline_id).map(Timeline::now)
+    }
+
+    /// The ID of the timeline added with this name, if any. The main timeline has no name.
+    pub fn timeline_id(&self, name: &str) -> Option<TimelineId> {
+        self.other_timelines
+            .iter()
+            .position(|(timeline_name, _)| timeline_name == name)
+            .map(|index| index as TimelineId + 1)
+    }
+
+    fn timeline_ref(&self, timeline_id: TimelineId) -> Option<&Timeline<Message, Topic>> {
+        match timeline_id {
+            MAIN_TIMELINE => Some(&self.timeline),
+            id            => self.other_timelines.get(id as usize - 1).map(|(_, timeline)| timeline),
+        }
+    }
+
+    fn timeline_mut(&mut self, timeline_id: TimelineId) -> Option<&mut Timeline<Message, Topic>> {
+        match timeline_id {
+            MAIN_TIMELINE => Some(&mut self.timeline),
+            id            => self.other_timelines.get_mut(id as usize - 1).map(|(_, timeline)| timeline),
+        }
+    }
+
+    /// The timeline with the earliest next event. Ties go to the lowest ID.
+    fn next_timeline(&self) -> Option<TimelineId> {
+        (0..=self.other_timelines.len() as TimelineId)
+            .filter_map(|id| Some((self.timeline_ref(id)?.next_time()?, id)))
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    /// Returns a handle that can pause and resume this router's event loop from another thread.
+    /// Routing `Channel::Pause` has the same effect as `PauseHandle::pause()`, but once the loop
+    /// is paused it processes no messages, so only a handle can resume it.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.simulation_paused.clone()
+    }
+
+    /// The current simulated time.
+    pub fn now(&self) -> Time {
+        self.timeline.now()
+    }
+
+    /// The number of messages waiting in the message queue.
+    pub fn queue_len(&self) -> usize {
+        self.message_queue.len()
+    }
+
+    /// Takes a snapshot of the simulation once the main timeline reaches `time`, without
+    /// stopping it, and sends it on the returned channel. The snapshot is taken just before the
+    /// first event at or after `time` is routed, so it reflects every event before `time`. If
+    /// `time` is not in the future, the snapshot is taken now. If the simulation never reaches
+    /// `time`, nothing is sent, and the channel disconnects when the `Router` is dropped.
+    /// A copy made with `clone_for_testing()` doesn't take the original's pending snapshots.
+    pub fn snapshot_at(&mut self, time: Time) -> Receiver<RouterSnapshot> {
+        let (sender, receiver) = mpsc::channel();
+        self.pending_snapshots.push((time, sender));
+        self.take_due_snapshots();
+        receiver
+    }
+
+    /// Takes the snapshots that are due at the current time and sends them.
+    fn take_due_snapshots(&mut self) {
+        let now = self.timeline.now();
+        if self.pending_snapshots.iter().all(|(time, _)| *time > now) {
+            return;
+        }
+
+        let snapshot = RouterSnapshot {
+            time          : now,
+            actors        : self
+                .actors
+                .iter()
+                .map(|actor| {
+                    let actor = actor.borrow();
+                    (actor.name().to_string(), actor.snapshot())
+                })
+                .collect(),
+            queue_len     : self.message_queue.len(),
+            pending_events: self.timeline.events().count(),
+        };
+        self.pending_snapshots.retain(|(time, sender)| {
+            if *time > now {
+                return true;
+            }
+            // Nobody is waiting for the snapshot if the receiver is gone, which is fine.
+            let _ = sender.send(snapshot.clone());
+            false
+        });
+    }
+
+    /// The number of actors subscribed to `channel`. Observers and directed routes are not
+    /// counted.
+    pub fn subscribe_count(&self, channel: &Channel<Topic>) -> usize {
+        self.subscriptions
+            .borrow()
+            .get(&channel.subscription_key())
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    /// The channels with at least one subscribed actor, in no particular order, e.g. for
+    /// drawing who listens to what. Like `subscribe_count()`, it ignores observers.
+    pub fn channels(&self) -> Vec<Channel<Topic>> {
+        self.subscriptions
+            .borrow()
+            .iter()
+            .filter(|(_, subscribers)| !subscribers.is_empty())
+            .map(|(channel, _)| channel.clone())
+            .collect()
+    }
+
+    /// The first actor whose `Actor::name()` is `name`. Actors that don't override `name()`
+    /// are named after their type.
+    pub fn actor_by_name(&self, name: &str) -> Option<RcActor<Message, Topic>> {
+        self.actors
+            .iter()
+            .find(|actor| actor.borrow().name() == name)
+            .cloned()
+    }
+
+    /// The handles, in order, of the actors in the group `prefix`, i.e. those named `prefix`
+    /// or `"prefix.something"`, e.g. `"disease"` for `"disease.infection_manager"` and
+    /// `"disease.transmission_manager"`. Groups nest: `"disease.transmission"` is a group, too.
+    /// Removed actors are left out.
+    pub fn actors_in_group(&self, prefix: &str) -> Vec<ActorHandle> {
+        let prefix = prefix.strip_suffix('.').unwrap_or(prefix);
+        (0..self.actors.len() as ActorHandle)
+            .filter(|handle| !self.removed.contains(handle))
+            .filter(|&handle| {
+                let actor = self.actors[handle as usize].borrow();
+                actor
+                    .name()
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+            .collect()
+    }
+
+    /// Like `actor_by_name()`, but returns the actor as its concrete type `A`, or `None` if
+    /// the actor with that name is not an `A` (see `share()`).
+    pub fn typed_actor_by_name<A>(&self, name: &str) -> Option<RcCell<A>>
+        where A: Actor<Message, Topic> + MaybeSend + 'static
+    {
+        let handle = self.actors.iter().position(|actor| actor.borrow().name() == name)?;
+        self.share(handle as ActorHandle)
+    }
+
+    /// The actor with the given handle as its concrete type `A`, or `None` if there is no such
+    /// actor, it is not an `A`, or it was added as an `RcActor`, which has forgotten its type
+    /// (see `IntoRcActor`). An actor that is handed a sibling this way, e.g. before it is
+    /// added, can call the sibling's methods directly instead of exchanging messages. The
+    /// sibling must not be borrowed while the `Router` is delivering a message to it.
+    pub fn share<A>(&self, handle: ActorHandle) -> Option<RcCell<A>>
+        where A: Actor<Message, Topic> + MaybeSend + 'static
+    {
+        let actor = self.actors_as_any.get(handle as usize)?.clone()?;
+        actor.downcast::<Cell<A>>().ok().map(RcCell)
+    }
+
+    /// The actors in this `Router`, indexed by `ActorHandle`.
+    pub fn actors(&self) -> &[RcActor<Message, Topic>] {
+        &self.actors
+    }
+
+    /// The IDs of the messages each message immediately caused, keyed by the ID of the cause.
+    /// Empty unless causal tracking is enabled (see `with_causal_tracking()`).
+    pub fn causal_graph(&self) -> &HashMap<u64, Vec<u64>> {
+        &self.causal_graph
+    }
+
+    pub fn timeline(&self) -> &Timeline<Message, Topic> {
+        &self.timeline
+    }
+
+    /// The times of all scheduled events, in no particular order.
+    pub fn timeline_events(&self) -> Vec<Time> {
+        self.timeline.events().map(|event| event.time).collect()
+    }
+
+    /// The times of all scheduled events, earliest first.
+    pub fn timeline_events_sorted(&self) -> Vec<Time> {
+        let mut times = self.timeline_events();
+        times.sort();
+        times
+    }
+
+    /// Whether `run()` is currently executing.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    /// Begins the event loop
+    ///
+    /// Panics if called re-entrantly, i.e. while `run()` is already executing.
+    pub fn run(&mut self) {
+        self.run_to(None);
+    }
+
+    /// Like `run()`, but returns once the message queue is empty and the next timeline event
+    /// is after `stop_time`, e.g. to collect results on day 100. Afterward `now()` is the time
+    /// of the last event processed, not `stop_time`, and the simulation can be continued with
+    /// `step()`, `run()`, or `run_until()` with a later time.
+    ///
+    /// Panics if called while `run()` is already executing.
+    pub fn run_until(&mut self, stop_time: Time) {
+        self.run_to(Some(stop_time));
+    }
+
+    fn run_to(&mut self, stop_time: Option<Time>) {
+        assert!(!self.is_running, "`Router::run()` called while the router is already running");
+
+        #[cfg(feature = "log")]
+        log::info!(actor_count = self.actors.len(), time:% = self.timeline.now(); "run started");
+
+        #[cfg(debug_assertions)]
+        for error in self.validate_subscriptions() {
+            self.raise_warning(Cow::Owned(error.to_string()));
+        }
+
+        self.set_up_actors();
+
+        self.is_running = true;
+        let start_time  = self.timeline.now();
+        if !self.simulation_started {
+            // Give actors a chance to do their startup work (e.g. loading data) before anything else.
+            self.simulation_started = true;
+            self.route(RcEnvelope::new(
+                Envelope::new(TIMELINE_HANDLE, Channel::SimulationStarted, None, Some(self.timeline.now()))
+            ));
+        }
+        let (stop_reason, steps) = self.event_loop(stop_time);
+        self.is_running = false;
+        self.run_result = Some(RunResult { stop_reason, steps, start_time, end_time: self.timeline.now() });
+
+        #[cfg(feature = "log")]
+        log::info!(actor_count = self.actors.len(), time:% = self.timeline.now(); "run finished");
+    }
+
+    /// The outcome of the last call to `run()`, or `None` if `run()` hasn't finished yet. A
+    /// stepping stone to `run()` returning its `RunResult` itself.
+    pub fn last_run_result(&self) -> Option<&RunResult> {
+        self.run_result.as_ref()
+    }
+
+    /// Returns why the loop ended and how many routing steps it took.
+    fn event_loop(&mut self, stop_time: Option<Time>) -> (StopReason, u64) {
+        let mut steps = 0;
+        loop {
+            if self.stop_requested {
+                eprintln!("Stopping early.");
+                return (StopReason::StopRequested, steps);
+            }
+
+            if self.simulation_paused.is_paused() {
+                thread::yield_now();
+                continue;
+            }
+
+            if self.debug_requested {
+                self.debug_requested = false;
+                DebugSession::start(self);
+                // The session may have stepped the simulation to a stop.
+                continue;
+            }
+
+            // Messages are drained before we look at the timeline, as in `process_next()`.
+            if let Some(stop_time) = stop_time
+                && self.message_queue.is_empty()
+                && let Some(timeline_id) = self.next_timeline()
+                && self.timeline_ref(timeline_id).and_then(Timeline::next_time).is_some_and(|time| time > stop_time)
+            {
+                return (StopReason::StopTimeReached, steps);
+            }
+
+            if !self.process_next() {
+                // All messages & events are exhausted.
+                return (StopReason::Exhausted, steps);
+            }
+            steps += 1;
+        }
+    }
+
+    /// Routes the next message in the message queue or, if the queue is empty, the next
+    /// timeline event, then delivers the deferred messages that are due, and returns what it
+    /// routed. Each call is one routing step of `run()`, even if there was nothing to route but
+    /// deferred messages are waiting. Unlike `run()`, it doesn't set up actors or announce
+    /// `Channel::SimulationStarted`, so tests can look at the actors after every message.
+    pub fn step(&mut self) -> StepResult<Message, Topic> {
+        let result = match self.process_next_message_or_event() {
+            StepResult::Exhausted if !self.deferred.is_empty() => StepResult::Waiting,
+            StepResult::Exhausted                              => return StepResult::Exhausted,
+            result                                             => result,
+        };
+        self.deliver_due_deferred();
+        result
+    }
+
+    /// Like `step()`, but returns `false` if there was nothing left to process.
+    pub(crate) fn process_next(&mut self) -> bool {
+        !matches!(self.step(), StepResult::Exhausted)
+    }
+
+    /// Counts down the steps of the deferred messages and routes those that are due, in the
+    /// order they were deferred.
+    fn deliver_due_deferred(&mut self) {
+        if self.deferred.is_empty() {
+            return;
+        }
+
+        let mut due = vec![];
+        self.deferred.retain_mut(|(steps_left, envelope)| {
+            *steps_left -= 1;
+            if *steps_left == 0 {
+                due.push(envelope.clone());
+            }
+            *steps_left > 0
+        });
+        for envelope in due {
+            self.route(envelope);
+        }
+    }
+
+    /// Routes the next message in the message queue or, if the queue is empty, the next
+    /// timeline event. Returns `StepResult::Exhausted` if there was nothing left to process.
+    fn process_next_message_or_event(&mut self) -> StepResult<Message, Topic> {
+        // Message queue processed before timeline. An actor might have placed something in
+        // the message queue that needs processing before we process the timeline.
+        // Each envelope is stamped before it is routed, so that the caller gets the envelope as
+        // it was delivered, and `route()`'s own stamping finds nothing left to change.
+        if let Some(envelope) = self.dequeue() {
+            let envelope = self.stamp(envelope);
+            self.route(envelope.clone());
+            return StepResult::Message(envelope);
+        }
+
+        let Some(timeline_id) = self.next_timeline() else {
+            return StepResult::Exhausted;
+        };
+
+        // A heartbeat is due before the next event.
+        if timeline_id == MAIN_TIMELINE
+            && let Some((interval, next_heartbeat)) = self.heartbeat
+            && let Some(next_event)             = self.timeline.next_time()
+            && next_heartbeat <= next_event
+        {
+            self.heartbeat = Some((interval, next_heartbeat + interval));
+            self.clock.sleep_until(next_heartbeat);
+            let heartbeat = RcEnvelope::new(
+                Envelope::new(TIMELINE_HANDLE, Channel::Heartbeat, None, Some(next_heartbeat))
+            );
+            let heartbeat = self.stamp(heartbeat);
+            self.route(heartbeat.clone());
+            return StepResult::Event(heartbeat);
+        }
+
+        let Some(event) = self.timeline_mut(timeline_id).and_then(Timeline::pop) else {
+            return StepResult::Exhausted;
+        };
+        let Event{ envelope: event_envelope, time, ..} = event;
+
+        if timeline_id == MAIN_TIMELINE {
+            self.clock.sleep_until(time);
+            self.take_due_snapshots();
+        }
+
+        let envelope = Envelope{
+            channel       : Channel::TimelineEvent,
+            time,
+            is_timed_event: true,
+            timeline_id,
+            message_id    : None, // The event is a new message.
+            children      : vec![], // Enqueued when the event was scheduled.
+            ..event_envelope.as_ref().clone()
+        };
+        let envelope = self.record_cause(event_envelope.message_id, RcEnvelope::new(envelope));
+        let envelope = self.stamp(envelope);
+        self.route(envelope.clone());
+        StepResult::Event(envelope)
+    }
+
+    /// Handles a single message in the message queue. Returns how many actors, including
+    /// observers, received it, so that `assert!(router.route(envelope) > 0)` catches a
+    /// message nobody subscribes to. A message the `Router` consumes itself, e.g. a
+    /// `Channel::Stop` or a `Channel::ScheduleEvent` it drops, counts as received by none.
+    ///
+    /// Once the envelope has been delivered, its `children` are enqueued, followed by the
+    /// responses to it. The children of an envelope the `Router` consumes are dropped with it.
+    pub fn route(&mut self, envelope: RcEnvelope<Message, Topic>) -> usize {
+        let envelope = self.stamp(envelope);
+
+        #[cfg(feature = "log")]
+        log::debug!(channel:? = envelope.channel, from = envelope.from, time:? = envelope.time; "routing");
+
+        // Process system messages
+        if self.act_on_system_message(envelope.clone()) {
+            // The `act_on_system_message()` function returns true if we should stop routing.
+            #[cfg(feature = "log")]
+            log::debug!(channel:? = envelope.channel, from = envelope.from; "consumed by router");
+            return 0;
+        }
+
+        let parent_id  = envelope.message_id;
+        let aggregated = matches!(envelope.channel, Channel::Aggregate(_));
+        let children   = envelope.children.clone();
+        let (delivered, responses) = self.deliver(envelope);
+        let responses = if children.is_empty() {
+            responses
+        } else {
+            responses.into_iter().map(|response| Self::strip_inherited_children(&children, response)).collect()
+        };
+        let children: Vec<_> = children.into_iter().map(|child| self.record_cause(parent_id, child)).collect();
+        self.enqueue(children);
+        let responses = self.limit_event_storms(responses);
+        let responses: Vec<_> = responses.into_iter().map(|response| self.record_cause(parent_id, response)).collect();
+        if aggregated {
+            // The batch is routed right away, so nothing already queued comes between its messages.
+            for response in responses {
+                self.route(response);
+            }
+        } else {
+            self.enqueue(responses);
+        }
+        delivered
+    }
+
+    /// Routes `envelope` on `Channel::Broadcast`, whatever channel it was addressed to, so that
+    /// every actor receives it. Returns how many actors did, like `route()`.
+    pub fn broadcast(&mut self, envelope: RcEnvelope<Message, Topic>) -> usize {
+        let envelope = Envelope { channel: Channel::Broadcast, ..envelope.as_ref().clone() };
+        self.route(RcEnvelope::new(envelope))
+    }
+
+    /// Removes from `response` the children it shares with its parent, e.g. because it was
+    /// built with `..envelope.clone()`, so that they aren't enqueued a second time.
+    fn strip_inherited_children(
+        parent_children: &[RcEnvelope<Message, Topic>],
+        mut response   : RcEnvelope<Message, Topic>
+    ) -> RcEnvelope<Message, Topic> {
+        let inherited = |child: &RcEnvelope<Message, Topic>| {
+            parent_children.iter().any(|parent_child| RcEnvelope::ptr_eq(parent_child, child))
+        };
+        if response.children.iter().any(inherited) {
+            RcEnvelope::make_mut(&mut response).children.retain(|child| !inherited(child));
+        }
+        response
+    }
+
+    /// With causal tracking, gives `child` its own message ID and records it as a child of
+    /// `parent_id`, which becomes its `correlation_id`.
+    fn record_cause(&mut self, parent_id: Option<u64>, mut child: RcEnvelope<Message, Topic>) -> RcEnvelope<Message, Topic> {
+        if !self.causal_tracking {
+            return child;
+        }
+
+        if child.message_id.is_none() || child.message_id == parent_id {
+            RcEnvelope::make_mut(&mut child).message_id = Some(self.next_message_id);
+            self.next_message_id += 1;
+        }
+        if child.correlation_id != parent_id {
+            RcEnvelope::make_mut(&mut child).correlation_id = parent_id;
+        }
+        if let Some(parent_id) = parent_id
+            && let Some(child_id) = child.message_id
+        {
+            self.causal_graph.entry(parent_id).or_default().push(child_id);
+        }
+        child
+    }
+
+    /// Drops the `ScheduleEvent` messages of any actor that sent more than the event storm limit.
+    fn limit_event_storms(&mut self, responses: Vec<RcEnvelope<Message, Topic>>) -> Vec<RcEnvelope<Message, Topic>> {
+        let Some(limit) = self.event_storm_limit else {
+            return responses;
+        };
+
+        let mut scheduled: HashMap<ActorHandle, usize> = HashMap::new();
+        let mut dropped  : Vec<ActorHandle>            = vec![];
+        let responses = responses
+            .into_iter()
+            .filter(|response| {
+                if response.channel != Channel::ScheduleEvent {
+                    return true;
+                }
+                let count = scheduled.entry(response.from).or_default();
+                *count += 1;
+                if *count == limit + 1 {
+                    dropped.push(response.from);
+                }
+                *count <= limit
+            })
+            .collect();
+
+        for handle in dropped {
+            self.raise_warning(Cow::Owned(format!(
+                "event storm detected: actor {} scheduled {} events in one step, more than the limit of {}",
+                handle,
+                scheduled[&handle],
+                limit
+            )));
+        }
+
+        responses
+    }
+
+    /// Assigns a message ID to an envelope that doesn't have one, sets the time of an
+    /// envelope that isn't a timed event to the current time, and makes a relative time
+    /// absolute.
+    fn stamp(&mut self, mut envelope: RcEnvelope<Message, Topic>) -> RcEnvelope<Message, Topic> {
+        let now = self.timeline.now();
+        if envelope.schedule_mode == ScheduleMode::RelativeToNow {
+            // Converted before anything else sees the envelope, so that `act_on_system_message()`
+            // schedules the event at `now` plus the delay and subscribers see the actual time.
+            // The delay is relative to the envelope's own timeline.
+            let timeline_now       = self.now_on(envelope.timeline_id).unwrap_or(now);
+            let envelope           = RcEnvelope::make_mut(&mut envelope);
+            envelope.time         += timeline_now;
+            envelope.schedule_mode = ScheduleMode::Absolute;
+        }
+        if envelope.message_id.is_none() || (!envelope.is_timed_event && envelope.time != now) {
+            // Usually the router holds the only reference, in which case this doesn't copy.
+            let envelope = RcEnvelope::make_mut(&mut envelope);
+            if envelope.message_id.is_none() {
+                envelope.message_id   = Some(self.next_message_id);
+                self.next_message_id += 1;
+            }
+            if !envelope.is_timed_event {
+                envelope.time = now;
+            }
+        }
+        envelope
+    }
+
+    /// Delivers the envelope to the (unpaused, unstopped) subscribers of its channel and returns
+    /// how many actors, including observers, received it, along with their responses.
+    fn deliver(&mut self, envelope: RcEnvelope<Message, Topic>) -> (usize, Vec<RcEnvelope<Message, Topic>>) {
+        if let Channel::RequestReply { inner, reply_to } = &envelope.channel {
+            // Subscribers of `inner` see an ordinary message on `inner`. We re-address
+            // whatever they respond with to `reply_to`.
+            let reply_to = reply_to.as_ref().clone();
+            let request  = Envelope { channel: inner.as_ref().clone(), ..envelope.as_ref().clone() };
+
+            let (delivered, responses) = self.deliver(RcEnvelope::new(request));
+            let responses = responses
+                .into_iter()
+                .map(|response| RcEnvelope::new(Envelope { channel: reply_to.clone(), ..response.as_ref().clone() }))
+                .collect();
+            return (delivered, responses);
+        }
+
+        if let Channel::Conditional { inner, predicate_id } = &envelope.channel {
+            let accepted = match self.predicates.get(predicate_id) {
+                Some(predicate) => predicate(&envelope),
+                None            => {
+                    self.raise_warning(Cow::Owned(format!(
+                        "actor {} sent a message conditional on unregistered predicate {}; dropping it",
+                        envelope.from,
+                        predicate_id
+                    )));
+                    false
+                }
+            };
+            if !accepted {
+                return (0, vec![]);
+            }
+
+            let delivered = Envelope { channel: inner.as_ref().clone(), ..envelope.as_ref().clone() };
+            return self.deliver(RcEnvelope::new(delivered));
+        }
+
+        // The envelope may name its recipients. Those that aren't live actors don't get it.
+        let recipients = match &envelope.channel {
+            Channel::Multicast(targets)                        => Some(self.live_recipients(&envelope, targets)),
+            Channel::Unicast(target) | Channel::Direct(target) => Some(self.live_recipients(&envelope, &[*target])),
+            _                                                  => None,
+        };
+
+        let key               = envelope.channel.subscription_key();
+        let mut subscriptions = self.subscriptions.borrow_mut();
+        let subscribers: Cow<[ActorHandle]> = match (recipients, &envelope.channel) {
+            (Some(recipients), _) => Cow::Owned(recipients),
+            // Heartbeats, tombstones, and broadcasts go to everyone.
+            (None, Channel::Heartbeat | Channel::Tombstone(_) | Channel::Broadcast) => {
+                Cow::Owned((0..self.actors.len() as ActorHandle).collect())
+            }
+            _ => match self.directed_routes.get(&(envelope.from, key.clone())) {
+                Some(targets) => Cow::Borrowed(targets.as_slice()),
+                None          => Cow::Borrowed(subscriptions.entry(key.clone()).or_default()),
+            },
+        };
+
+        // Gossip reaches a random few of the subscribers, in the order they subscribed.
+        let subscribers = match envelope.channel {
+            Channel::Gossip { fanout } if fanout < subscribers.len() => {
+                let mut chosen = index::sample(&mut self.gossip_rng, subscribers.len(), fanout).into_vec();
+                chosen.sort_unstable();
+                Cow::Owned(chosen.into_iter().map(|index| subscribers[index]).collect())
+            }
+            // The first few subscribers get the message, the rest don't.
+            Channel::Exactly(n, _) if n < subscribers.len() => match subscribers {
+                Cow::Borrowed(subscribers) => Cow::Borrowed(&subscribers[..n]),
+                Cow::Owned(mut subscribers) => {
+                    subscribers.truncate(n);
+                    Cow::Owned(subscribers)
+                }
+            },
+            // Round robin reaches the subscribers one at a time, in turn.
+            Channel::RoundRobin(_) if !subscribers.is_empty() => {
+                let next   = self.round_robin_state.entry(key.clone()).or_default();
+                let chosen = subscribers[*next % subscribers.len()];
+                *next      = (*next + 1) % subscribers.len();
+                Cow::Owned(vec![chosen])
+            }
+            _ => subscribers,
+        };
+
+        // Forwarding targets get the message too, but only once.
+        let subscribers = match self.forwards.get(&key) {
+            Some(targets) => {
+                let mut subscribers = subscribers.into_owned();
+                for &target in targets {
+                    if !subscribers.contains(&target) {
+                        subscribers.push(target);
+                    }
+                }
+                Cow::Owned(subscribers)
+            }
+            None => subscribers,
+        };
+
+        #[cfg(feature = "log")]
+        log::debug!(channel:? = envelope.channel, subscribers = subscribers.len(), paused = self.paused.len(); "delivering");
+
+        if subscribers.is_empty() && !self.observer_subscriptions.contains_key(&key) {
+            self.messages_dropped += 1;
+        }
+        *self.channel_message_counts.entry(envelope.channel.clone()).or_default() += 1;
+
+        // Actors that turn the message away in `Actor::pre_receive()` don't receive it.
+        let active: Vec<ActorHandle> = subscribers
+            .iter()
+            .copied()
+            .filter(|handle| !self.paused.contains(handle) && !self.stopped.contains(handle))
+            .filter(|&handle| self.actors[handle as usize].borrow_mut().pre_receive(&envelope))
+            .collect();
+        let results = self.dispatcher.dispatch(&active, &self.actors, envelope.clone());
+
+        drop(subscriptions);
+
+        let mut delivered = results.len();
+        let mut responses = vec![];
+        if let Channel::Aggregate(_) = envelope.channel {
+            // Delivery is synchronous, so the collection window closes before `deliver()`
+            // returns: every subscriber has answered by the time the dispatcher is done.
+            let mut barrier = AggregateBarrier::new(results.len());
+            for (handle, result) in results {
+                barrier.respond(self.handle_actor_result(handle, result));
+            }
+            responses = barrier.into_batch().unwrap_or_default();
+        } else {
+            for (handle, result) in results {
+                responses.extend(self.handle_actor_result(handle, result));
+            }
+        }
+
+        let mut warnings = vec![];
+        if let Some(indices) = self.observer_subscriptions.get(&key) {
+            for &index in indices {
+                let observer = &self.observers[index];
+                // Observers can't affect the simulation, so we only report their errors.
+                let result   = observer.borrow_mut().receive_message(&envelope);
+                delivered   += 1;
+                match result {
+                    Ok(ignored) => warnings.extend(Self::discard_observer_messages(observer, ignored)),
+                    Err(error)  => warnings.push(Cow::Owned(format!("observer {} returned {}", observer.borrow().name(), error))),
+                }
+            }
+        }
+        for warning in warnings {
+            self.raise_warning(warning);
+        }
+
+        (delivered, responses)
+    }
+
+    /// Factored out from `route()`, returns true if `route()` should return without routing.
+    fn act_on_system_message(&mut self, envelope: RcEnvelope<Message, Topic>) -> bool {
+        // Check for system- or timeline-specific messages
+        match envelope.as_ref() {
+            Envelope { channel: Channel::ScheduleEvent, is_timed_event: true, timeline_id, .. } if self.timeline_ref(*timeline_id).is_none() => {
+                self.raise_warning(Cow::Owned(format!(
+                    "actor {} scheduled an event on nonexistent timeline {}; dropping it",
+                    envelope.from,
+                    timeline_id
+                )));
+                true
+            }
+
+            Envelope { channel: Channel::ScheduleEvent, is_timed_event: true, time, timeline_id, .. } if Some(*time) < self.now_on(*timeline_id) => {
+                // The event can't happen in the past, so we drop it.
+                self.raise_warning(Cow::Owned(format!(
+                    "actor {} scheduled an event at time {} in the past (now {}); dropping it",
+                    envelope.from,
+                    time,
+                    self.now_on(*timeline_id).unwrap_or_default()
+                )));
+                true
+            }
+
+            Envelope { channel: Channel::ScheduleEvent, is_timed_event: true, time, timeline_id, .. } => {
+                let event = Event::new(*time, envelope.clone());
+                if let Some(timeline) = self.timeline_mut(*timeline_id) {
+                    timeline.push(event);
+                }
+                // We do not return, because other actors might wish to act on timeline messages
+                false
+            }
+
+            Envelope { channel: Channel::Time, is_timed_event: false, message: None, .. } => {
+                // If there is no time or message, it's a request for the current time. A
+                // message means it's some other time-related query for an actor to answer.
+                // We answer with the time on the timeline the request names, the main one by default.
+                let now          = self.now_on(envelope.timeline_id).unwrap_or(self.timeline.now());
+                let new_envelope = Envelope::new(ActorHandle::default(), Channel::Time, None, Some(now))
+                    .on_timeline(envelope.timeline_id);
+                #[cfg(feature = "print_messages")]
+                println!("ROUTER/TIMELINE: {:?}", new_envelope);
+                self.enqueue([RcEnvelope::new(new_envelope)]);
+                false
+            }
+
+            Envelope { channel: Channel::Stop, .. } => {
+                self.stop_requested = true;
+                // ToDo: Should we return without routing anything else?
+                true
+            }
+
+            Envelope { channel: Channel::Pause, .. } => {
+                // Our own copy of the flag is the only one, so nothing could ever resume us.
+                if Arc::strong_count(&self.simulation_paused.paused) == 1 {
+                    self.raise_warning(Cow::Borrowed("ignoring pause request: no `PauseHandle` exists to resume"));
+                } else {
+                    self.simulation_paused.pause();
+                }
+                true
+            }
+
+            Envelope { channel: Channel::Resume, .. } => {
+                self.simulation_paused.resume();
+                true
+            }
+
+            Envelope { channel: Channel::Echo, from, .. } => {
+                // The sender receives the echo if it subscribes to `Channel::Actor(from)`.
+                let echo = Envelope { channel: Channel::Actor(*from), ..envelope.as_ref().clone() };
+                self.enqueue([RcEnvelope::new(echo)]);
+                true
+            }
+
+            Envelope { channel: Channel::Defer { delay_steps, inner }, .. } => {
+                let deferred = RcEnvelope::new(Envelope { channel: inner.as_ref().clone(), ..envelope.as_ref().clone() });
+                if *delay_steps == 0 {
+                    self.enqueue([deferred]);
+                } else {
+                    self.deferred.push_back((*delay_steps, deferred));
+                }
+                true
+            }
+
+            Envelope { channel: Channel::BulkTopic(_), from, .. } => {
+                self.raise_warning(Cow::Owned(format!(
+                    "actor {} sent a message on Channel::BulkTopic, which is for subscribing only; it was dropped",
+                    from
+                )));
+                true
+            }
+
+            Envelope { channel: Channel::Debug, .. } => {
+                self.debug_requested = true;
+                // ToDo: Should we return without routing anything else?
+                true
+            }
+
+            _ => {
+                // Not a system message
+                false
+            }
+        } // end match
+    }
+
+    /// Returns the actor's responses, or acts on its error and returns nothing. A fatal error
+    /// stops the actor after giving it a chance to clean up in `Actor::on_error()`.
+    fn handle_actor_result(
+        &mut self,
+        handle: ActorHandle,
+        result: ActorResult<Message, Topic>
+    ) -> Vec<RcEnvelope<Message, Topic>> {
+        let error = match result {
+            Ok(responses) => {
+                if !responses.is_empty() {
+                    *self.messages_emitted.entry(handle).or_default() += responses.len() as u64;
+                }
+                return responses;
+            }
+            Err(error) => error,
+        };
+
+        let actor = self.actors[handle as usize].clone();
+        if let ActorError::Fatal(_) = error {
+            actor.borrow_mut().on_error(&error);
+            self.stopped.insert(handle);
+        }
+        let name = actor.borrow().name().to_string();
+        self.raise_warning(Cow::Owned(format!("actor {} ({}) returned {}", handle, name, error)));
+        self.stop_fail_fast_group(handle);
+
+        vec![]
+    }
+
+    /// Stops every actor in the fail-fast group of `handle`, if it has one, sending each that
+    /// isn't already stopped a `Channel::Stop` message first.
+    fn stop_fail_fast_group(&mut self, handle: ActorHandle) {
+        let Some(index) = self.fail_fast_groups.iter().position(|group| group.contains(&handle)) else {
+            return;
+        };
+        // A stopped group can't fail again, so it no longer needs to be tracked.
+        let group = self.fail_fast_groups.swap_remove(index);
+        let stop  = Envelope::new(TIMELINE_HANDLE, Channel::Stop, None, Some(self.timeline.now()));
+
+        for &member in &group {
+            if self.stopped.insert(member) {
+                // The group is going down regardless, so what the member answers is ignored.
+                let _ = self.actors[member as usize].borrow_mut().receive_message(&stop);
+            }
+        }
+        self.raise_warning(Cow::Owned(format!("actor {} failed, so its group {:?} was stopped", handle, group)));
+    }
+
+    /// Processes system messages without broadcasting to non system actors. For non system messages,
+    /// Routes the envelope, but collects the responses in a vector and returns them instead of
+    /// putting them in a queue.
+    ///
+    /// This is useful for testing / debugging.
+    pub fn silent_route(&mut self, envelope: RcEnvelope<Message, Topic>) -> Vec<RcEnvelope<Message, Topic>> {
+        let envelope = self.stamp(envelope);
+
+        // Process system messages
+        if self.act_on_system_message(envelope.clone()) {
+            // The `act_on_system_message()` function returns true if we should stop routing.
+            return vec![];
+        }
+
+        // Instead of adding the responses the message queue, we return them.
+        let parent_id      = envelope.message_id;
+        let (_, responses) = self.deliver(envelope);
+        responses.into_iter().map(|response| self.record_cause(parent_id, response)).collect()
+    }
+
+}
+
+/// What an actor can do with the `Router` from `Actor::setup()`, whatever the `Router`'s
+/// `Dispatcher`.
+pub trait SetupContext<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+    /// Subscribes the actor with `handle` to `channel` (see `Router::subscribe()`).
+    fn subscribe(&mut self, handle: ActorHandle, channel: Channel<Topic>);
+
+    /// Appends the envelope to the message queue. The actor being set up is borrowed, so the
+    /// message is delivered once the run starts rather than right away.
+    fn enqueue(&mut self, envelope: RcEnvelope<Message, Topic>);
+
+    /// The first actor named `name` (see `Router::actor_by_name()`).
+    fn actor_by_name(&self, name: &str) -> Option<RcActor<Message, Topic>>;
+
+    /// The actors, indexed by `ActorHandle` (see `Router::actors()`).
+    fn actors(&self) -> &[RcActor<Message, Topic>];
+}
+
+impl<Message, Topic, D> SetupContext<Message, Topic> for Router<Message, Topic, D>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
+{
+    fn subscribe(&mut self, handle: ActorHandle, channel: Channel<Topic>) {
+        Router::subscribe(self, handle, channel);
+    }
+
+    fn enqueue(&mut self, envelope: RcEnvelope<Message, Topic>) {
+        Router::enqueue(self, [envelope]);
+    }
+
+    fn actor_by_name(&self, name: &str) -> Option<RcActor<Message, Topic>> {
+        Router::actor_by_name(self, name)
+    }
+
+    fn actors(&self) -> &[RcActor<Message, Topic>] {
+        Router::actors(self)
+    }
+}
+
+impl<Message, Topic, D> Router<Message, Topic, D>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
+{
+    /// Calls `Actor::setup()` on each actor that hasn't been set up yet, in order of handle,
+    /// including any that are added during setup.
+    fn set_up_actors(&mut self) {
+        while self.actors_set_up < self.actors.len() {
+            let handle = self.actors_set_up as ActorHandle;
+            self.actors_set_up += 1;
+            if self.removed.contains(&handle) {
+                continue;
+            }
+
+            let actor = self.actors[handle as usize].clone();
+            actor.borrow_mut().setup(self);
+        }
+    }
+}
+
+/// `router[handle]` is the actor with that handle. `Index` can only return a plain reference,
+/// not the borrow guard a `&dyn Actor` would need to outlive, so this returns the actor's
+/// `RcActor`: write `router[handle].borrow()` to inspect the actor.
+///
+/// Panics if there is no actor with that handle.
+impl<Message, Topic, D> Index<ActorHandle> for Router<Message, Topic, D>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic,
+          D      : Dispatcher<Message, Topic>
+{
+    type Output = RcActor<Message, Topic>;
+
+    fn index(&self, handle: ActorHandle) -> &Self::Output {
+        &self.actors[handle as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        actor::{Actor, Registration},
+        dispatcher::ShuffledDispatcher,
+        rc_cell,
+        rccell::RcCell
+    };
+    use super::*;
+
+    type TestRouter = Router<u32, u8>;
+
+    /// Appends `(own handle, message)` to a shared log for every envelope it receives.
+    struct Recorder {
+        handle       : ActorHandle,
+        subscriptions: Vec<Channel<u8>>,
+        log          : RcCell<Vec<(ActorHandle, u32)>>,
+    }
+
+    impl Recorder {
+        fn new(subscriptions: Vec<Channel<u8>>, log: RcCell<Vec<(ActorHandle, u32)>>) -> Self {
+            Recorder { handle: 0, subscriptions, log }
+        }
+    }
+
+    impl Actor<u32, u8> for Recorder {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            self.log.borrow_mut().push((self.handle, envelope.message.unwrap_or_default()));
+            Ok(vec![])
+        }
+
+        fn register(&mut self, handle: ActorHandle) -> Registration<u32, u8> {
+            self.handle = handle;
+            Ok((self.subscriptions.clone(), vec![]))
+        }
+    }
+
+    fn envelope(channel: Channel<u8>, message: u32) -> RcEnvelope<u32, u8> {
+        RcEnvelope::new(Envelope::new(0, channel, Some(message), None))
+    }
+
+    #[test]
+    fn paused_actor_is_skipped_until_resumed() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        router.pause_actor(0);
+        router.route(envelope(Channel::General, 1));
+        router.resume_actor(0);
+        router.route(envelope(Channel::General, 2));
+
+        assert_eq!(*log.borrow(), vec![(1, 1), (0, 2), (1, 2)]);
+    }
+
+    /// Counts the even messages it receives and turns the odd ones away.
+    struct EvenCounter {
+        count: u32,
+    }
+
+    impl Actor<u32, u8> for EvenCounter {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            assert_eq!(envelope.message.unwrap() % 2, 0);
+            self.count += 1;
+            Ok(vec![])
+        }
+
+        fn pre_receive(&mut self, envelope: &Envelope<u32, u8>) -> bool {
+            envelope.message.is_some_and(|message| message % 2 == 0)
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    fn pre_receive_filters_messages() {
+        let log        = RcCell::new(vec![]);
+        let counter    = rc_cell!(EvenCounter { count: 0 });
+        let mut router = TestRouter::new();
+        router.add_actor(RcCell(counter.0.clone())).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        for message in 1..=4 {
+            router.route(envelope(Channel::General, message));
+        }
+
+        assert_eq!(counter.borrow().count, 2);
+        // Other subscribers are unaffected.
+        assert_eq!(*log.borrow(), vec![(1, 1), (1, 2), (1, 3), (1, 4)]);
+    }
+
+    /// Answers every message it receives with the same message on `Channel::General`.
+    struct Responder;
+
+    impl Actor<u32, u8> for Responder {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![RcEnvelope::new(Envelope { channel: Channel::General, ..envelope.clone() })])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::Topic(1)], vec![]))
+        }
+    }
+
+    /// Answers everything on `channel` with the same message on `Channel::General`.
+    struct Answerer {
+        channel: Channel<u8>,
+    }
+
+    impl Actor<u32, u8> for Answerer {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![RcEnvelope::new(Envelope { channel: Channel::General, ..envelope.clone() })])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![self.channel.clone()], vec![]))
+        }
+    }
+
+    /// Counts the messages it receives.
+    #[derive(Clone)]
+    struct Counter {
+        count: u32,
+    }
+
+    impl Actor<u32, u8> for Counter {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            self.count += 1;
+            Ok(vec![])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+
+        fn clone_actor(&self) -> Option<RcActor<u32, u8>> {
+            Some(rc_cell!(self.clone()))
+        }
+    }
+
+    #[test]
+    fn cloned_router_is_independent() {
+        let counter    = rc_cell!(Counter { count: 0 });
+        let mut router = TestRouter::new();
+        router.add_actor(RcCell(counter.0.clone())).unwrap();
+        router.route(envelope(Channel::General, 1));
+
+        let mut clone = router.clone_for_testing();
+        clone.route(envelope(Channel::General, 2));
+        clone.route(envelope(Channel::General, 3));
+
+        assert_eq!(counter.borrow().count, 1);
+    }
+
+    #[test]
+    fn subscribers_receive_messages_in_registration_order() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        // The first actor subscribes to more channels so that the subscription lists differ.
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(2), Channel::Topic(3)], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(3)], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(3)], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::Topic(3), 1));
+        router.route(envelope(Channel::Topic(3), 2));
+
+        assert_eq!(*log.borrow(), vec![(0, 1), (1, 1), (2, 1), (0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn observer_responses_are_discarded() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_observer(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        // The observer answers on `Channel::General`, but the answer never reaches the recorder.
+        router.route(envelope(Channel::Topic(1), 1));
+        router.run();
+
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn aggregated_responses_are_routed_as_a_batch() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Answerer { channel: Channel::Aggregate(5) })).unwrap();
+        router.add_actor(rc_cell!(Answerer { channel: Channel::Aggregate(5) })).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.enqueue([envelope(Channel::General, 9)]);
+
+        assert_eq!(router.route(envelope(Channel::Aggregate(5), 1)), 2);
+        // Both answers arrive before the message that was already queued.
+        assert_eq!(*log.borrow(), vec![(2, 1), (2, 1)]);
+        router.run();
+        assert_eq!(*log.borrow(), vec![(2, 1), (2, 1), (2, 9)]);
+    }
+
+    #[test]
+    fn aggregate_barrier_waits_for_every_subscriber() {
+        let mut barrier = AggregateBarrier::<u32, u8>::new(2);
+        barrier.respond([envelope(Channel::General, 1)]);
+        assert!(!barrier.is_complete());
+        barrier.respond([]);
+        assert_eq!(barrier.into_batch().map(|batch| batch.len()), Some(1));
+
+        let mut incomplete = AggregateBarrier::<u32, u8>::new(2);
+        incomplete.respond([envelope(Channel::General, 1)]);
+        assert!(incomplete.into_batch().is_none());
+    }
+
+    #[test]
+    fn broadcast_reaches_every_actor() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+
+        assert_eq!(router.broadcast(envelope(Channel::Topic(2), 1)), 3);
+        // Actors added later hear broadcasts too.
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        assert_eq!(router.route(envelope(Channel::Broadcast, 2)), 4);
+        assert_eq!(*log.borrow(), vec![(0, 1), (2, 1), (0, 2), (2, 2), (3, 2)]);
+
+        // The responder's answers to the broadcasts are enqueued as usual.
+        assert_eq!(router.queue_len(), 2);
+        while router.process_next() {}
+        assert_eq!(*log.borrow(), vec![(0, 1), (2, 1), (0, 2), (2, 2), (3, 2), (0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn exactly_reaches_the_first_subscribers() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        for _ in 0..3 {
+            router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(1)], log.clone()))).unwrap();
+        }
+
+        assert_eq!(router.route(envelope(Channel::Exactly(1, 1), 1)), 1);
+        assert_eq!(router.route(envelope(Channel::Exactly(2, 1), 2)), 2);
+        assert_eq!(router.route(envelope(Channel::Exactly(usize::MAX, 1), 3)), 3);
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 2), (1, 2), (0, 3), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        for _ in 0..3 {
+            router.add_actor(rc_cell!(Recorder::new(vec![Channel::RoundRobin(1)], log.clone()))).unwrap();
+        }
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::RoundRobin(2)], log.clone()))).unwrap();
+
+        for message in 1..=4 {
+            assert_eq!(router.route(envelope(Channel::RoundRobin(1), message)), 1);
+        }
+        // Each topic keeps its own rotation.
+        router.route(envelope(Channel::RoundRobin(2), 5));
+        router.route(envelope(Channel::RoundRobin(1), 6));
+
+        assert_eq!(*log.borrow(), vec![(0, 1), (1, 2), (2, 3), (0, 4), (3, 5), (1, 6)]);
+    }
+
+    #[test]
+    fn gossip_reaches_fanout_subscribers() {
+        let gossip = |seed| {
+            let log        = RcCell::new(vec![]);
+            let mut router = TestRouter::new().with_gossip_seed(seed);
+            for _ in 0..5 {
+                router.add_actor(rc_cell!(Recorder::new(vec![Channel::gossip()], log.clone()))).unwrap();
+            }
+            assert_eq!(router.route(envelope(Channel::Gossip { fanout: 2 }, 1)), 2);
+            assert_eq!(router.route(envelope(Channel::Gossip { fanout: 9 }, 2)), 5);
+            log.borrow().clone()
+        };
+
+        let heard = gossip(3);
+        assert!(heard[0].0 < heard[1].0);
+        assert_eq!(heard[2..].iter().map(|(handle, _)| *handle).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(gossip(3), heard);
+    }
+
+    #[test]
+    fn route_counts_recipients() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_observer(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        assert_eq!(router.route(envelope(Channel::General, 1)), 3);
+        assert_eq!(router.route(envelope(Channel::Topic(1), 1)), 0);
+        // The router consumes stop messages itself.
+        assert_eq!(router.route(envelope(Channel::Stop, 1)), 0);
+    }
+
+    #[test]
+    fn top_emitter_sent_the_most_responses() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Answerer { channel: Channel::Topic(2) })).unwrap();
+        router.add_actor(rc_cell!(Answerer { channel: Channel::Topic(3) })).unwrap();
+        assert_eq!(router.top_emitter(), None);
+
+        router.route(envelope(Channel::Topic(3), 1));
+        router.route(envelope(Channel::Topic(2), 1));
+        // A tie goes to the lower handle.
+        assert_eq!(router.top_emitter(), Some(0));
+        router.route(envelope(Channel::Topic(3), 1));
+        assert_eq!(router.top_emitter(), Some(1));
+    }
+
+    #[test]
+    fn unsubscribed_messages_are_counted_as_dropped() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        router.add_observer(rc_cell!(Responder)).unwrap();
+
+        router.route(envelope(Channel::General, 1));
+        router.route(envelope(Channel::Topic(1), 1));
+        assert_eq!(router.messages_dropped(), 0);
+
+        router.route(envelope(Channel::Topic(2), 1));
+        assert_eq!(router.messages_dropped(), 1);
+    }
+
+    #[test]
+    fn messages_are_counted_per_channel() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        assert!(router.channel_message_counts().is_empty());
+
+        router.route(envelope(Channel::General, 1));
+        router.route(envelope(Channel::General, 2));
+        router.route(envelope(Channel::Topic(2), 1));
+        router.route(envelope(Channel::RequestReply {
+            inner   : Box::new(Channel::Topic(2)),
+            reply_to: Box::new(Channel::General),
+        }, 1));
+        router.route(envelope(Channel::Stop, 0));
+
+        let counts = router.channel_message_counts();
+        assert_eq!(counts, HashMap::from([("General".to_string(), 2), ("Topic(2)".to_string(), 2)]));
+    }
+
+    #[test]
+    fn dedup_drops_queued_duplicates() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new().with_dedup(true);
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        router.enqueue([envelope(Channel::General, 1), envelope(Channel::General, 1), envelope(Channel::General, 2)]);
+        assert_eq!(router.queue_len(), 2);
+        router.run();
+        // Once the first copy has been routed, the message can be queued again.
+        router.enqueue([envelope(Channel::General, 1)]);
+        router.run();
+
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 2), (0, 1)]);
+    }
+
+    #[test]
+    fn heartbeats_reach_every_actor() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new().with_heartbeat(1.0.into());
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        router.push_timeline_event(2.5.into(), envelope(Channel::ScheduleEvent, 9));
+        router.run();
+
+        // Heartbeats at 1 and 2, then the event at 2.5, which the recorder doesn't subscribe to.
+        assert_eq!(*log.borrow(), vec![(0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn pushed_events_skip_routing() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::ScheduleEvent, Channel::TimelineEvent], log.clone()))).unwrap();
+        router.push_timeline_event(2.0.into(), envelope(Channel::ScheduleEvent, 7));
+
+        // Nobody hears about the event until it fires.
+        assert!(log.borrow().is_empty());
+        assert_eq!(router.queue_len(), 0);
+        router.run();
+
+        assert_eq!(*log.borrow(), vec![(0, 7)]);
+        assert_eq!(router.now(), Time::from(2.0));
+    }
+
+    #[test]
+    fn scheduling_in_the_past_raises_warning() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::warnings()], log.clone()))).unwrap();
+        router.push_timeline_event(5.0.into(), envelope(Channel::General, 0));
+        router.process_next();
+
+        router.route(RcEnvelope::new(Envelope::new(0, Channel::ScheduleEvent, Some(1), Some(1.0.into()))));
+        router.run();
+
+        assert_eq!(*log.borrow(), vec![(0, 0)]);
+        assert_eq!(router.now(), Time::from(5.0));
+    }
+
+    /// Fails on every message on `Channel::General`, with the kind of error given by `fatal`.
+    struct Failing {
+        fatal     : bool,
+        received  : u32,
+        cleaned_up: bool,
+    }
+
+    impl Actor<u32, u8> for Failing {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            self.received += 1;
+            if self.fatal {
+                Err(ActorError::Fatal("broken".to_string()))
+            } else {
+                Err(ActorError::Recoverable("try again".to_string()))
+            }
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+
+        fn on_error(&mut self, _error: &ActorError) {
+            self.cleaned_up = true;
+        }
+    }
+
+    #[test]
+    fn timeline_events_lists_scheduled_times() {
+        let mut router = TestRouter::new();
+        for time in [3.0, 1.0, 2.0] {
+            router.push_timeline_event(Time::from(time), envelope(Channel::General, 0));
+        }
+
+        assert_eq!(router.timeline_events().len(), 3);
+        assert_eq!(router.timeline_events_sorted(), vec![Time::from(1.0), Time::from(2.0), Time::from(3.0)]);
+    }
+
+    #[test]
+    fn snapshots_are_taken_on_the_way() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        for time in [1.0, 2.0, 3.0] {
+            router.route(RcEnvelope::new(Envelope::new(0, Channel::ScheduleEvent, Some(1), Some(time.into()))));
+        }
+        let now   = router.snapshot_at(0.0.into());
+        let later = router.snapshot_at(2.5.into());
+        let never = router.snapshot_at(9.0.into());
+
+        assert_eq!(now.try_recv().unwrap().pending_events, 3);
+        assert!(later.try_recv().is_err());
+        router.run();
+
+        // The snapshot was taken when the event at 3.0 was about to be routed.
+        let later = later.try_recv().unwrap();
+        assert_eq!(later.time, Time::from(3.0));
+        assert_eq!(later.pending_events, 0);
+        assert_eq!(later.actors.len(), 1);
+        assert!(never.try_recv().is_err());
+    }
+
+    #[test]
+    fn relative_events_are_scheduled_after_now() {
+        let mut router = TestRouter::new();
+        router.push_timeline_event(Time::from(2.0), envelope(Channel::General, 0));
+        router.run();
+
+        let schedule = Envelope::new(0, Channel::ScheduleEvent, Some(1), Some(Time::from(3.0))).relative_to_now();
+        router.route(RcEnvelope::new(schedule));
+
+        assert_eq!(router.timeline_events(), vec![Time::from(5.0)]);
+    }
+
+    #[test]
+    fn queue_alarm_fires_once_per_crossing() {
+        let alarms: Arc<std::sync::Mutex<Vec<usize>>> = Arc::default();
+        let log        = alarms.clone();
+        let mut router = TestRouter::new().with_queue_alarm(2, Box::new(move |len| log.lock().unwrap().push(len)));
+        router.add_actor(rc_cell!(Responder)).unwrap();
+
+        // Each message is answered once on `Channel::General`, which nobody subscribes to.
+        for message in 0..4 {
+            router.route(envelope(Channel::Topic(1), message));
+        }
+        assert_eq!(*alarms.lock().unwrap(), vec![3]);
+
+        while router.process_next() {}
+        for message in 0..3 {
+            router.route(envelope(Channel::Topic(1), message));
+        }
+        assert_eq!(*alarms.lock().unwrap(), vec![3, 3]);
+    }
+
+    #[test]
+    fn timelines_keep_their_own_clocks() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::TimelineEvent], log.clone()))).unwrap();
+        let calendar   = router.add_timeline("calendar");
+        assert_eq!(router.timeline_id("calendar"), Some(calendar));
+
+        let event = |message, time: f64, timeline_id| {
+            RcEnvelope::new(Envelope::new(0, Channel::ScheduleEvent, Some(message), Some(Time::from(time))).on_timeline(timeline_id))
+        };
+        router.route(event(1, 2.0, MAIN_TIMELINE));
+        router.route(event(2, 1.0, calendar));
+        router.route(event(3, 3.0, calendar));
+        router.route(event(4, 1.0, 7));
+        router.run();
+
+        // Events from both timelines interleave by time.
+        assert_eq!(*log.borrow(), vec![(0, 2), (0, 1), (0, 3)]);
+        assert_eq!(router.now(), Time::from(2.0));
+        assert_eq!(router.now_on(calendar), Some(Time::from(3.0)));
+        assert_eq!(router.now_on(7), None);
+    }
+
+    #[test]
+    fn fatal_error_stops_actor() {
+        let log        = RcCell::new(vec![]);
+        let fatal      = rc_cell!(Failing { fatal: true, received: 0, cleaned_up: false });
+        let recovering = rc_cell!(Failing { fatal: false, received: 0, cleaned_up: false });
+        let mut router = TestRouter::new();
+        router.add_actor(RcCell(fatal.0.clone())).unwrap();
+        router.add_actor(RcCell(recovering.0.clone())).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::warnings()], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::General, 1));
+        router.route(envelope(Channel::General, 2));
+        router.run();
+
+        assert_eq!(fatal.borrow().received, 1);
+        assert!(fatal.borrow().cleaned_up);
+        assert_eq!(recovering.borrow().received, 2);
+        assert!(!recovering.borrow().cleaned_up);
+        // One warning for the fatal error and one for each recoverable error.
+        assert_eq!(log.borrow().len(), 3);
+    }
+
+    #[test]
+    fn fail_fast_group_stops_together() {
+        let failing    = rc_cell!(Failing { fatal: false, received: 0, cleaned_up: false });
+        let member     = rc_cell!(Counter { count: 0 });
+        let bystander  = rc_cell!(Counter { count: 0 });
+        let mut router = TestRouter::new();
+        let group: Vec<RcActor<u32, u8>> = vec![RcCell(failing.0.clone()), RcCell(member.0.clone())];
+        assert_eq!(router.add_actor_group(group, GroupStrategy::FailFast), Ok(vec![0, 1]));
+        router.add_actor_group(vec![RcCell(bystander.0.clone())], GroupStrategy::Independent).unwrap();
+
+        router.route(envelope(Channel::General, 1));
+        router.route(envelope(Channel::General, 2));
+
+        // Each member got the first message and the stop, but not the second message.
+        assert_eq!(failing.borrow().received, 2);
+        assert_eq!(member.borrow().count, 2);
+        assert_eq!(bystander.borrow().count, 2);
+    }
+
+    #[test]
+    fn actor_pool_adds_count_actors() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+
+        let handles = router.with_actor_pool(
+            |index| -> RcActor<u32, u8> { rc_cell!(Recorder::new(vec![Channel::Topic(index as u8)], log.clone())) },
+            3
+        );
+        assert_eq!(handles, Ok(vec![1, 2, 3]));
+
+        // Each worker was made for its own index.
+        router.route(envelope(Channel::Topic(2), 1));
+        assert_eq!(*log.borrow(), vec![(3, 1)]);
+    }
+
+    #[test]
+    fn paused_simulation_waits_for_resume() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        let handle     = router.pause_handle();
+
+        router.route(RcEnvelope::new(Envelope::new(0, Channel::Pause, None, None)));
+        assert!(handle.is_paused());
+        router.route(RcEnvelope::new(Envelope::new(0, Channel::Resume, None, None)));
+        assert!(!handle.is_paused());
+
+        router.route(RcEnvelope::new(Envelope::new(0, Channel::Pause, None, None)));
+        router.message_queue.push_back(envelope(Channel::General, 1));
+        let started = std::time::Instant::now();
+        let resumer = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(20));
+            handle.resume();
+        });
+        router.run();
+        resumer.join().unwrap();
+
+        assert!(started.elapsed() >= std::time::Duration::from_millis(20));
+        assert_eq!(*log.borrow(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn directed_route_bypasses_subscribers() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        router.add_directed_route(5, 2, Channel::General);
+
+        router.route(RcEnvelope::new(Envelope::new(5, Channel::General, Some(1), None)));
+        // Other senders still reach the subscribers.
+        router.route(RcEnvelope::new(Envelope::new(6, Channel::General, Some(2), None)));
+
+        assert_eq!(*log.borrow(), vec![(2, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn forwarded_channels_reach_the_proxy_too() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(2)], log.clone()))).unwrap();
+        router.forward_to(1, vec![Channel::General, Channel::Topic(2)]);
+
+        router.route(envelope(Channel::General, 1));
+        // The proxy already subscribes to `Topic(2)`, so it only gets the message once.
+        router.route(envelope(Channel::Topic(2), 2));
+
+        assert_eq!(*log.borrow(), vec![(0, 1), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn untimed_envelopes_are_stamped_with_now() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.push_timeline_event(3.0.into(), envelope(Channel::General, 0));
+        router.process_next();
+
+        let responses = router.silent_route(envelope(Channel::Topic(1), 7));
+        assert_eq!(responses[0].time, Time::from(3.0));
+        assert!(!responses[0].is_timed_event);
+    }
+
+    #[test]
+    fn children_are_enqueued_before_responses() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        let children = vec![envelope(Channel::General, 2), envelope(Channel::General, 3)];
+        let parent   = Envelope::new(0, Channel::Topic(1), Some(1), None).with_children(children);
+        router.route(RcEnvelope::new(parent));
+        assert_eq!(router.queue_len(), 3);
+        router.run();
+
+        // The responder's echo of the parent comes after the parent's children, and doesn't
+        // bring them along again even though it copied them.
+        assert_eq!(*log.borrow(), vec![(1, 2), (1, 3), (1, 1)]);
+    }
+
+    #[test]
+    fn routed_envelopes_get_increasing_ids() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+
+        let first  = router.silent_route(envelope(Channel::Topic(1), 1));
+        let second = router.silent_route(envelope(Channel::Topic(1), 2));
+        // Responses are copies of the request, so they carry its ID.
+        assert_eq!(first[0].message_id, Some(0));
+        assert_eq!(second[0].message_id, Some(1));
+
+        let mut preset    = Envelope::new(0, Channel::Topic(1), Some(3), None);
+        preset.message_id = Some(99);
+        let third         = router.silent_route(RcEnvelope::new(preset));
+        assert_eq!(third[0].message_id, Some(99));
+    }
+
+    #[cfg(feature = "send")]
+    #[test]
+    fn router_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<TestRouter>();
+    }
+
+    /// Schedules `count` events whenever it receives a message.
+    struct Storm {
+        count: u32,
+    }
+
+    impl Actor<u32, u8> for Storm {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok((0..self.count)
+                .map(|i| RcEnvelope::new(Envelope::new(0, Channel::ScheduleEvent, Some(i), Some(1.0.into()))))
+                .collect())
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    fn event_storm_is_cut_off() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new().with_event_storm_limit(3);
+        router.add_actor(rc_cell!(Storm { count: 10 })).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::warnings()], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::General, 0));
+        // Three `ScheduleEvent` messages and the warning
+        assert_eq!(router.queue_len(), 4);
+        while router.queue_len() > 0 {
+            router.process_next();
+        }
+        assert_eq!(router.timeline().as_sorted_vec().len(), 3);
+        assert_eq!(log.borrow().len(), 1);
+    }
+
+    #[test]
+    fn actors_can_be_found_by_name() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        router.route(envelope(Channel::General, 1));
+
+        let name = std::any::type_name::<Counter>();
+        assert!(router.actor_by_name(name).is_some());
+        assert!(router.actor_by_name("nobody").is_none());
+        assert_eq!(router.typed_actor_by_name::<Counter>(name).unwrap().borrow().count, 1);
+        assert!(router.typed_actor_by_name::<Responder>(name).is_none());
+    }
+
+    /// Does nothing but answer to its name.
+    struct Named(&'static str);
+
+    impl Actor<u32, u8> for Named {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![], vec![]))
+        }
+
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[test]
+    fn actors_can_be_found_by_group() {
+        let mut router = TestRouter::new();
+        for name in ["disease.infection_manager", "reporting", "disease.transmission.network", "diseases", "disease"] {
+            router.add_actor(rc_cell!(Named(name))).unwrap();
+        }
+
+        assert_eq!(router.actors_in_group("disease"), vec![0, 2, 4]);
+        assert_eq!(router.actors_in_group("disease."), vec![0, 2, 4]);
+        assert_eq!(router.actors_in_group("disease.transmission"), vec![2]);
+        assert!(router.actors_in_group("disease.infection").is_empty());
+
+        router.remove_actor(0).unwrap();
+        assert_eq!(router.actors_in_group("disease"), vec![2, 4]);
+    }
+
+    #[test]
+    fn shared_actor_is_the_routed_actor() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        let counter = router.share::<Counter>(0).unwrap();
+
+        router.route(envelope(Channel::General, 1));
+
+        assert_eq!(counter.borrow().count, 1);
+        assert!(router.share::<Responder>(0).is_none());
+        assert!(router.share::<Counter>(1).is_none());
+    }
+
+    /// Claims to be a `Counter` every way it can.
+    struct Impostor;
+
+    impl Impostor {
+        #[allow(dead_code)]
+        fn actor_type_id(&self) -> std::any::TypeId {
+            std::any::TypeId::of::<Counter>()
+        }
+    }
+
+    impl Actor<u32, u8> for Impostor {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+
+        fn name(&self) -> &str {
+            std::any::type_name::<Counter>()
+        }
+    }
+
+    #[test]
+    fn share_cannot_be_tricked_into_the_wrong_type() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Impostor)).unwrap();
+        let counter: RcActor<u32, u8> = rc_cell!(Counter { count: 0 });
+        router.add_actor(counter).unwrap();
+
+        assert!(router.share::<Counter>(0).is_none());
+        assert!(router.typed_actor_by_name::<Counter>(std::any::type_name::<Counter>()).is_none());
+        assert!(router.share::<Impostor>(0).is_some());
+        // An `RcActor` has forgotten its type, so even a real `Counter` can't be recovered.
+        assert!(router.share::<Counter>(1).is_none());
+    }
+
+    /// A `Counter` that reports a version.
+    struct VersionedCounter {
+        version: u32,
+        counter: Counter,
+    }
+
+    impl Actor<u32, u8> for VersionedCounter {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            self.counter.receive_message(envelope)
+        }
+
+        fn register(&mut self, handle: ActorHandle) -> Registration<u32, u8> {
+            self.counter.register(handle)
+        }
+
+        fn version(&self) -> u32 {
+            self.version
+        }
+    }
+
+    #[test]
+    fn replaced_actor_takes_over_subscriptions() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(VersionedCounter { version: 1, counter: Counter { count: 0 } })).unwrap();
+        router.route(envelope(Channel::General, 1));
+
+        let stale = router.replace_actor(0, rc_cell!(VersionedCounter { version: 1, counter: Counter { count: 0 } }));
+        assert_eq!(stale, Err(RouterError::VersionNotNewer { handle: 0, current: 1, offered: 1 }));
+        assert_eq!(router.replace_actor(1, rc_cell!(Counter { count: 0 })), Err(RouterError::NoSuchActor { handle: 1 }));
+
+        let newer = rc_cell!(VersionedCounter { version: 2, counter: Counter { count: 0 } });
+        router.replace_actor(0, RcCell(newer.0.clone())).unwrap();
+        router.route(envelope(Channel::General, 2));
+
+        assert_eq!(newer.borrow().counter.count, 1);
+        assert_eq!(router.subscribe_count(&Channel::General), 1);
+    }
+
+    /// Refuses registration unless another actor was added before it.
+    struct Dependent;
+
+    impl Actor<u32, u8> for Dependent {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![])
+        }
+
+        fn register(&mut self, handle: ActorHandle) -> Registration<u32, u8> {
+            if handle == 0 {
+                return Err(RegistrationError("no sibling to depend on".to_string()));
+            }
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    fn actor_can_refuse_registration() {
+        let mut router = TestRouter::new();
+        let refused = router.add_actor(rc_cell!(Dependent));
+        assert_eq!(refused, Err(RegistrationError("no sibling to depend on".to_string())));
+        assert!(router.actors().is_empty());
+        assert_eq!(router.subscribe_count(&Channel::General), 0);
+
+        assert_eq!(router.add_actor(rc_cell!(Counter { count: 0 })), Ok(0));
+        assert_eq!(router.add_actor(rc_cell!(Dependent)), Ok(1));
+        assert_eq!(router.subscribe_count(&Channel::General), 2);
+    }
+
+    #[test]
+    fn actors_can_be_indexed_by_handle() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+
+        assert_eq!(router[1].borrow().name(), std::any::type_name::<Counter>());
+        assert_eq!(router[0].borrow().name(), std::any::type_name::<Responder>());
+    }
+
+    #[test]
+    fn echo_returns_to_sender() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Actor(0)], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Echo], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::Echo, 5));
+        router.run();
+
+        // Only the sender sees the echo, and the router consumes the original.
+        assert_eq!(*log.borrow(), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn causal_graph_links_messages_to_responses() {
+        let mut router = TestRouter::new().with_causal_tracking(true);
+        // Answers on `Channel::General`, which the counter subscribes to.
+        router.add_actor(rc_cell!(Responder)).unwrap();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+
+        router.route(envelope(Channel::Topic(1), 1));
+        let response = router.message_queue.front().unwrap();
+        assert_eq!((response.message_id, response.correlation_id), (Some(1), Some(0)));
+        while router.process_next() {}
+
+        // Message 0 caused the response 1, which the counter received without responding.
+        assert_eq!(router.causal_graph(), &HashMap::from([(0, vec![1])]));
+
+        let untracked = TestRouter::new();
+        assert!(untracked.causal_graph().is_empty());
+    }
+
+    #[test]
+    fn subscriptions_of_removed_actors_are_orphaned() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        assert!(router.validate_subscriptions().is_empty());
+
+        router.remove_actor(1).unwrap();
+        assert!(router.validate_subscriptions().is_empty());
+        // A stale subscription, e.g. made with the handle of an actor that is gone.
+        router.subscribe(1, Channel::General);
+
+        assert_eq!(
+            router.validate_subscriptions(),
+            vec![RouterError::OrphanedSubscription { channel: Channel::General, handle: 1 }]
+        );
+    }
+
+    #[test]
+    fn multicast_reaches_only_listed_actors() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        for _ in 0..3 {
+            router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        }
+
+        router.route(envelope(Channel::Multicast(vec![2, 0]), 1));
+
+        assert_eq!(*log.borrow(), vec![(2, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn multicast_skips_actors_that_are_not_live() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        for _ in 0..3 {
+            router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        }
+        router.remove_actor(1).unwrap();
+        while router.process_next() {}
+        log.borrow_mut().clear();
+
+        router.route(envelope(Channel::Multicast(vec![2, 7, 1, 0]), 1));
+
+        assert_eq!(*log.borrow(), vec![(2, 1), (0, 1)]);
+        // One warning for each of the two handles that aren't live actors.
+        assert_eq!(router.queue_len(), 2);
+    }
+
+    /// Sends `count` messages to `target` on `Channel::Unicast` whenever it receives one on `Channel::General`.
+    struct UnicastSender {
+        target: ActorHandle,
+        count : u32,
+    }
+
+    impl Actor<u32, u8> for UnicastSender {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok((0..self.count).map(|i| envelope(Channel::Unicast(self.target), i)).collect())
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    fn unicast_reaches_only_its_target() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+
+        assert_eq!(router.route(envelope(Channel::Unicast(1), 7)), 1);
+        assert_eq!(*log.borrow(), vec![(1, 7)]);
+    }
+
+    #[test]
+    fn direct_message_to_a_nonexistent_actor_is_dropped_with_a_warning() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+        router.remove_actor(1).unwrap();
+        while router.process_next() {}
+        log.borrow_mut().clear();
+
+        router.route(envelope(Channel::Direct(5), 1));
+        assert!(matches!(router.message_queue.back().map(|e| &e.channel), Some(Channel::Warning(_))));
+        router.route(envelope(Channel::Direct(1), 2));
+        assert_eq!(router.queue_len(), 2);
+        router.route(envelope(Channel::Direct(0), 3));
+
+        assert_eq!(*log.borrow(), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn direct_message_skips_the_queue_limit() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new().with_per_actor_queue_limit(1);
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+
+        router.enqueue([envelope(Channel::Direct(1), 1), envelope(Channel::Direct(1), 2)]);
+        assert_eq!(router.queue_len(), 2);
+        while router.process_next() {}
+        assert_eq!(*log.borrow(), vec![(1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn full_actor_queue_blocks_unicast_sender() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new().with_per_actor_queue_limit(1);
+        router.add_actor(rc_cell!(UnicastSender { target: 1, count: 3 })).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::General, 0));
+        // The sender was blocked until the first two messages were received.
+        assert_eq!(*log.borrow(), vec![(1, 0), (1, 1)]);
+        assert_eq!(router.queue_len(), 1);
+        assert_eq!(router.max_queue_depth(), 1);
+
+        while router.process_next() {}
+        assert_eq!(*log.borrow(), vec![(1, 0), (1, 1), (1, 2)]);
+
+        // Without a limit, nothing is received until the queue is processed.
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(UnicastSender { target: 1, count: 3 })).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::General, 0));
+        assert!(log.borrow().is_empty());
+        assert_eq!(router.queue_len(), 3);
+    }
+
+    #[test]
+    fn unicast_to_a_nonexistent_actor_is_dropped_without_filling_a_queue() {
+        let mut router = TestRouter::new().with_per_actor_queue_limit(1);
+        router.add_actor(rc_cell!(UnicastSender { target: 5, count: 3 })).unwrap();
+
+        router.route(envelope(Channel::General, 0));
+        // Nothing is counted toward the nonexistent actor's queue, so the sender isn't blocked.
+        assert_eq!(router.queue_len(), 3);
+        assert!(router.unicast_queue_depths.is_empty());
+
+        router.process_next();
+        assert!(matches!(router.message_queue.back().map(|e| &e.channel), Some(Channel::Warning(_))));
+        while router.process_next() {}
+    }
+
+    /// Logs its `on_stop()` call and every message it receives.
+    struct Departing {
+        events: RcCell<Vec<String>>,
+    }
+
+    impl Actor<u32, u8> for Departing {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            self.events.borrow_mut().push("received".to_string());
+            Ok(vec![])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+
+        fn on_stop(&mut self) {
+            self.events.borrow_mut().push("on_stop".to_string());
+        }
+    }
+
+    /// Logs the tombstones it receives.
+    struct Mourner {
+        handle: ActorHandle,
+        events: RcCell<Vec<String>>,
+    }
+
+    impl Actor<u32, u8> for Mourner {
+        fn receive_message(&mut self, envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            if let Channel::Tombstone(removed) = envelope.channel {
+                self.events.borrow_mut().push(format!("actor {} mourns {}", self.handle, removed));
+            }
+            Ok(vec![])
+        }
+
+        fn register(&mut self, handle: ActorHandle) -> Registration<u32, u8> {
+            self.handle = handle;
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    fn removed_actor_is_mourned_and_forgotten() {
+        let events     = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Mourner { handle: 0, events: events.clone() })).unwrap();
+        router.add_actor(rc_cell!(Departing { events: events.clone() })).unwrap();
+        router.add_actor(rc_cell!(Mourner { handle: 0, events: events.clone() })).unwrap();
+
+        router.remove_actor(1).unwrap();
+        assert_eq!(*events.borrow(), vec!["on_stop", "actor 0 mourns 1", "actor 2 mourns 1"]);
+
+        assert_eq!(router.subscribe_count(&Channel::General), 2);
+        router.route(envelope(Channel::General, 0));
+        assert_eq!(events.borrow().len(), 3);
+
+        assert_eq!(router.remove_actor(1), Err(RouterError::NoSuchActor { handle: 1 }));
+        assert_eq!(router.remove_actor(3), Err(RouterError::NoSuchActor { handle: 3 }));
+    }
+
+    /// Subscribes to `Channel::Topic(5)` and greets everyone on `Channel::General` during setup,
+    /// recording how many actors it saw.
+    struct Configurer {
+        handle: ActorHandle,
+        setups: RcCell<Vec<usize>>,
+    }
+
+    impl Actor<u32, u8> for Configurer {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            Ok(vec![])
+        }
+
+        fn register(&mut self, handle: ActorHandle) -> Registration<u32, u8> {
+            self.handle = handle;
+            Ok((vec![], vec![]))
+        }
+
+        fn setup(&mut self, router: &mut dyn SetupContext<u32, u8>) {
+            self.setups.borrow_mut().push(router.actors().len());
+            router.subscribe(self.handle, Channel::Topic(5));
+            router.enqueue(envelope(Channel::General, 9));
+        }
+    }
+
+    #[test]
+    fn actors_are_set_up_once_before_running() {
+        let log        = RcCell::new(vec![]);
+        let setups     = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Configurer { handle: 0, setups: setups.clone() })).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        assert!(setups.borrow().is_empty());
+
+        router.run();
+        assert_eq!(*setups.borrow(), vec![2]);
+        assert_eq!(router.subscribe_count(&Channel::Topic(5)), 1);
+        assert_eq!(*log.borrow(), vec![(1, 9)]);
+
+        router.run();
+        assert_eq!(*setups.borrow(), vec![2]);
+
+        // Whatever the dispatcher.
+        let setups     = RcCell::new(vec![]);
+        let mut router = Router::<u32, u8, _>::with_dispatcher(ShuffledDispatcher::new(7));
+        router.add_actor(rc_cell!(Configurer { handle: 0, setups: setups.clone() })).unwrap();
+        router.run();
+        assert_eq!(*setups.borrow(), vec![1]);
+        assert_eq!(router.subscribe_count(&Channel::Topic(5)), 1);
+    }
+
+    fn defer(delay_steps: usize, message: u32) -> RcEnvelope<u32, u8> {
+        envelope(Channel::Defer { delay_steps, inner: Box::new(Channel::General) }, message)
+    }
+
+    #[test]
+    fn deferred_message_waits_for_its_steps() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(1)], log.clone()))).unwrap();
+
+        assert_eq!(router.route(defer(2, 7)), 0);
+        router.enqueue([envelope(Channel::Topic(1), 1), envelope(Channel::Topic(1), 2), envelope(Channel::Topic(1), 3)]);
+        assert!(log.borrow().is_empty());
+
+        router.process_next();
+        assert_eq!(*log.borrow(), vec![(1, 1)]);
+        router.process_next();
+        assert_eq!(*log.borrow(), vec![(1, 1), (1, 2), (0, 7)]);
+        router.process_next();
+        assert_eq!(*log.borrow(), vec![(1, 1), (1, 2), (0, 7), (1, 3)]);
+
+        // Without a delay, the message is simply enqueued.
+        router.route(defer(0, 8));
+        assert_eq!(router.queue_len(), 1);
+    }
+
+    #[test]
+    fn deferred_message_is_delivered_when_nothing_else_is_left() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+
+        router.route(defer(2, 9));
+        assert!(router.process_next());
+        assert!(log.borrow().is_empty());
+        assert!(router.process_next());
+        assert_eq!(*log.borrow(), vec![(0, 9)]);
+        assert!(!router.process_next());
+    }
+
+    #[test]
+    fn step_routes_one_item_at_a_time() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.push_timeline_event(1.0.into(), envelope(Channel::General, 2));
+        router.enqueue([envelope(Channel::General, 1)]);
+
+        // The message queue comes before the timeline. Both come back as they were routed.
+        let StepResult::Message(routed) = router.step() else { panic!("expected a message") };
+        assert_eq!(routed.message, Some(1));
+        assert!(routed.message_id.is_some());
+        assert_eq!(routed.time.0, 0.0);
+        assert_eq!(*log.borrow(), vec![(0, 1)]);
+        let StepResult::Event(routed) = router.step() else { panic!("expected an event") };
+        assert_eq!((routed.message, &routed.channel), (Some(2), &Channel::TimelineEvent));
+        assert!(routed.message_id.is_some());
+        assert_eq!(routed.time.0, 1.0);
+
+        router.route(defer(1, 3));
+        assert!(matches!(router.step(), StepResult::Waiting));
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 3)]);
+        assert!(matches!(router.step(), StepResult::Exhausted));
+    }
+
+    #[test]
+    fn run_result_is_kept_after_run() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        router.push_timeline_event(2.0.into(), envelope(Channel::General, 1));
+        assert_eq!(router.last_run_result(), None);
+
+        router.run();
+        assert_eq!(router.last_run_result(), Some(&RunResult {
+            stop_reason: StopReason::Exhausted,
+            steps      : 1,
+            start_time : 0.0.into(),
+            end_time   : 2.0.into(),
+        }));
+
+        router.enqueue([envelope(Channel::Stop, 0), envelope(Channel::General, 1)]);
+        router.run();
+        let result = router.last_run_result().unwrap();
+        assert_eq!(result.stop_reason, StopReason::StopRequested);
+        assert_eq!(result.steps, 1);
+        assert_eq!(result.start_time.0, 2.0);
+    }
+
+    #[test]
+    fn is_running_is_cleared_when_run_returns() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Counter { count: 0 })).unwrap();
+        assert!(!router.is_running());
+
+        router.push_timeline_event(1.0.into(), envelope(Channel::General, 1));
+        router.run();
+        assert!(!router.is_running());
+
+        // Including when the run stops early.
+        router.enqueue([envelope(Channel::Stop, 0), envelope(Channel::General, 1)]);
+        router.run();
+        assert_eq!(router.last_run_result().unwrap().stop_reason, StopReason::StopRequested);
+        assert!(!router.is_running());
+    }
+
+    /// Calls `run()` on the router that is delivering to it, through a pointer, since there is
+    /// no safe way to. A raw pointer isn't `Send`, so not with the `send` feature.
+    #[cfg(not(feature = "send"))]
+    struct Reentrant {
+        router: *mut TestRouter,
+    }
+
+    #[cfg(not(feature = "send"))]
+    impl Actor<u32, u8> for Reentrant {
+        fn receive_message(&mut self, _envelope: &Envelope<u32, u8>) -> ActorResult<u32, u8> {
+            // SAFETY: This aliases the `&mut` that the outer `run()` holds, which is the misuse
+            // `run()` must catch. It panics before it touches the router's state.
+            unsafe { (*self.router).run() };
+            Ok(vec![])
+        }
+
+        fn register(&mut self, _handle: ActorHandle) -> Registration<u32, u8> {
+            Ok((vec![Channel::General], vec![]))
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "send"))]
+    #[should_panic(expected = "already running")]
+    fn reentrant_run_panics() {
+        let mut router = TestRouter::new();
+        let pointer    = &raw mut router;
+        router.add_actor(rc_cell!(Reentrant { router: pointer })).unwrap();
+        router.enqueue([envelope(Channel::General, 1)]);
+
+        router.run();
+    }
+
+    #[test]
+    fn run_until_stops_before_later_events() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::TimelineEvent], log.clone()))).unwrap();
+        for time in [1, 3, 5] {
+            router.push_timeline_event((time as f64).into(), envelope(Channel::General, time));
+        }
+
+        // An event at the stop time still happens.
+        router.run_until(3.0.into());
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 3)]);
+        assert_eq!(router.now(), 3.0);
+        assert_eq!(router.last_run_result().unwrap().stop_reason, StopReason::StopTimeReached);
+
+        router.run_until(4.0.into());
+        assert_eq!(log.borrow().len(), 2);
+        assert_eq!(router.now(), 3.0);
+
+        router.run();
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 3), (0, 5)]);
+        assert_eq!(router.last_run_result().unwrap().stop_reason, StopReason::Exhausted);
+    }
+
+    #[test]
+    fn cancelled_events_never_fire() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::TimelineEvent], log.clone()))).unwrap();
+        let calendar   = router.add_timeline("calendar");
+        for message in [1, 2, 3] {
+            router.push_timeline_event((message as f64).into(), envelope(Channel::General, message));
+        }
+        router.push_timeline_event(
+            2.5.into(),
+            RcEnvelope::new(Envelope::new(0, Channel::General, Some(2), None).on_timeline(calendar))
+        );
+
+        assert_eq!(router.cancel_events(|event| event.envelope.message == Some(2)), 2);
+        router.run();
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 3)]);
+    }
+
+    #[test]
+    fn channels_lists_subscribed_channels() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::Topic(1), Channel::General], RcCell::new(vec![])))).unwrap();
+        // Routing to a channel nobody subscribes to doesn't list it.
+        router.route(envelope(Channel::Topic(2), 1));
+
+        let mut channels = router.channels();
+        channels.sort_by_key(|channel| format!("{:?}", channel));
+        assert_eq!(channels, vec![Channel::General, Channel::Topic(1)]);
+    }
+
+    #[test]
+    fn bulk_topic_subscribes_to_each_topic() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::BulkTopic(vec![1, 2])], log.clone()))).unwrap();
+
+        router.route(envelope(Channel::Topic(1), 1));
+        router.route(envelope(Channel::Topic(2), 2));
+        router.route(envelope(Channel::Topic(3), 3));
+        router.route(envelope(Channel::BulkTopic(vec![1, 2]), 4));
+
+        assert_eq!(*log.borrow(), vec![(0, 1), (0, 2)]);
+        assert_eq!(router.subscribe_count(&Channel::Topic(1)), 1);
+        assert_eq!(router.subscribe_count(&Channel::BulkTopic(vec![1, 2])), 0);
+        assert!(matches!(router.message_queue.back().map(|e| &e.channel), Some(Channel::Warning(_))));
+    }
+
+    #[test]
+    fn request_reply_readdresses_responses() {
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Responder)).unwrap();
+
+        let request   = Channel::RequestReply {
+            inner   : Box::new(Channel::Topic(1)),
+            reply_to: Box::new(Channel::Topic(2)),
+        };
+        let responses = router.silent_route(envelope(request, 7));
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].channel, Channel::Topic(2));
+        assert_eq!(responses[0].message, Some(7));
+    }
+
+    #[test]
+    fn conditional_delivery_applies_predicate() {
+        let log        = RcCell::new(vec![]);
+        let mut router = TestRouter::new();
+        router.add_actor(rc_cell!(Recorder::new(vec![Channel::General], log.clone()))).unwrap();
+        router.register_predicate(1, Box::new(|envelope| envelope.message.is_some_and(|message| message % 2 == 0)));
+
+        let conditional = |predicate_id| Channel::Conditional { inner: Box::new(Channel::General), predicate_id };
+        router.route(envelope(conditional(1), 1));
+        router.route(envelope(conditional(1), 2));
+        router.route(envelope(conditional(2), 4));
+
+        assert_eq!(*log.borrow(), vec![(0, 2)]);
+        assert_eq!(router.queue_len(), 1); // The warning about predicate 2
+    }
 }
@@ -11,6 +11,7 @@ use std::{
 };
 
 use ordered_float::OrderedFloat;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 use crate::{
   message::RcEnvelope,
@@ -19,9 +20,43 @@ use crate::{
 
 pub type Time = OrderedFloat<f64>;
 
+/// Identifies one of a `Router`'s timelines (see `Router::add_timeline()`).
+pub type TimelineId = u32;
+/// The `Router`'s own timeline, which every `Router` has.
+pub const MAIN_TIMELINE: TimelineId = 0;
+
+/// How the `Router` interprets the `time` of a `Channel::ScheduleEvent` envelope.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScheduleMode {
+  /// `time` is when the event happens. This is the default.
+  #[default]
+  Absolute,
+  /// `time` is how long after the current time the event happens. The `Router` converts the
+  /// envelope to `Absolute` when it is routed, so subscribers only ever see absolute times.
+  RelativeToNow,
+}
+
+/// How a `Timeline` orders events scheduled for the same time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+  /// In the order they were scheduled. This is the default.
+  #[default]
+  OldestFirst,
+  /// In the reverse of the order they were scheduled.
+  NewestFirst,
+  /// By the handle of the actor that scheduled them, then oldest first.
+  ActorAscending,
+  /// In a random order determined by the seed.
+  Random(u64),
+}
+
 
 // region Event
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Event<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
@@ -30,18 +65,49 @@ pub struct Event<Message, Topic>
   /// We take the simple approach of just taking an `Envelope` and letting the scheduler
   /// decide its contents. This way there is a `to` and `from` built-in.
   pub envelope: RcEnvelope<Message, Topic>,
+  /// A human-readable description of the event for debug output, taken from the
+  /// scheduling envelope's `description`. Like the envelope's, it is not serialized.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub description: Option<&'static str>,
+  /// A short identifier for the event, e.g. `"attempt_infection"`, taken from the scheduling
+  /// envelope's `name`. Not serialized.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub name: Option<&'static str>,
+  /// The number of events pushed onto the timeline before this one. Assigned by `Timeline::push()`.
+  pub sequence_number: u64,
+  /// Orders events with equal times according to the timeline's `TieBreak`. Assigned by
+  /// `Timeline::push()`.
+  tie_break: u128,
   // We could also record the actor who scheduled the event, etc.
 }
 
+impl<Message, Topic> Event<Message, Topic>
+    where Message: Clone + Debug,
+          Topic  : BoundedTopic
+{
+  /// An event at `time`, with the envelope's `description` and `name`.
+  pub fn new(time: Time, envelope: RcEnvelope<Message, Topic>) -> Self {
+    Event {
+      time,
+      description: envelope.description,
+      name       : envelope.name,
+      envelope,
+      sequence_number: 0,
+      tie_break      : 0,
+    }
+  }
+}
+
 // Implements ordering of events in the timeline's priority queue. This is necessary because `BinaryHeap` is a max heap, not a min heap, and we want a min heap.
 //
-// Be warned that `Event`s are equal if they are scheduled at the same time regardless of envelope.
+// Be warned that `Event`s are equal if they are scheduled at the same time with the same tie
+// break, regardless of envelope.
 impl<Message, Topic> PartialEq for Event<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
 {
   fn eq(&self, other: &Self) -> bool {
-    self.time == other.time
+    self.time == other.time && self.tie_break == other.tie_break
   }
 }
 impl<Message, Topic> Eq for Event<Message, Topic>
@@ -53,7 +119,7 @@ impl<Message, Topic> PartialOrd for Event<Message, Topic>
           Topic  : BoundedTopic
 {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-    Some(self.cmp(&other))
+    Some(self.cmp(other))
   }
 }
 impl<Message, Topic> Ord for Event<Message, Topic>
@@ -61,18 +127,24 @@ impl<Message, Topic> Ord for Event<Message, Topic>
           Topic  : BoundedTopic
 {
   fn cmp(&self, other: &Self) -> Ordering {
-    Reverse(self.time).cmp(&Reverse(other.time))
+    Reverse((self.time, self.tie_break)).cmp(&Reverse((other.time, other.tie_break)))
   }
 }
 
 // endregion Event
 
+#[derive(Clone)]
 pub struct Timeline<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
 {
   now         : Time,
   event_queue : BinaryHeap<Event<Message, Topic>>,
+  tie_break   : TieBreak,
+  /// The sequence number of the next event pushed.
+  next_sequence_number: u64,
+  /// Used by `TieBreak::Random`.
+  rng         : SmallRng,
   // actor_handle: ActorHandle,
 }
 
@@ -81,11 +153,7 @@ where Message: Clone + Debug,
       Topic  : BoundedTopic
 {
   fn default() -> Self {
-    Self {
-      now         : Time::default(),
-      event_queue : BinaryHeap::new(),
-      // actor_handle: ActorHandle::default(),
-    }
+    Self::new_with_tie_break(TieBreak::default())
   }
 }
 
@@ -94,27 +162,194 @@ impl<Message, Topic> Timeline<Message, Topic>
     where Message: Clone + Debug,
           Topic  : BoundedTopic
 {
+  /// Creates an empty timeline that orders events with equal times according to `strategy`.
+  pub fn new_with_tie_break(strategy: TieBreak) -> Self {
+    let seed = match strategy {
+      TieBreak::Random(seed) => seed,
+      _                      => 0,
+    };
+
+    Self {
+      now         : Time::default(),
+      event_queue : BinaryHeap::new(),
+      tie_break   : strategy,
+      next_sequence_number: 0,
+      rng         : SmallRng::seed_from_u64(seed),
+      // actor_handle: ActorHandle::default(),
+    }
+  }
+
   #[inline(always)]
   pub fn now(&self) -> Time {
     self.now
   }
 
+  /// Schedules the event, assigning its sequence number.
   #[inline(always)]
-  pub fn push(&mut self, event: Event<Message, Topic>) {
+  pub fn push(&mut self, mut event: Event<Message, Topic>) {
+    let sequence_number = self.next_sequence_number;
+    self.next_sequence_number += 1;
+
+    event.sequence_number = sequence_number;
+    event.tie_break       = match self.tie_break {
+      TieBreak::OldestFirst    => sequence_number as u128,
+      TieBreak::NewestFirst    => (u64::MAX - sequence_number) as u128,
+      TieBreak::ActorAscending => ((event.envelope.from as u128) << 64) | sequence_number as u128,
+      TieBreak::Random(_)      => self.rng.random::<u64>() as u128,
+    };
     self.event_queue.push(event)
   }
 
+  /// How events with equal times are ordered.
+  pub fn tie_break(&self) -> TieBreak {
+    self.tie_break
+  }
+
+  /// The time of the next event, without removing it.
+  #[inline(always)]
+  pub(crate) fn next_time(&self) -> Option<Time> {
+    self.peek().map(|event| event.time)
+  }
+
+  /// The next event, without removing it or advancing `now`.
+  #[inline(always)]
+  pub fn peek(&self) -> Option<&Event<Message, Topic>> {
+    self.event_queue.peek()
+  }
+
+  /// The number of scheduled events.
+  #[inline(always)]
+  pub fn len(&self) -> usize {
+    self.event_queue.len()
+  }
+
+  #[inline(always)]
+  pub fn is_empty(&self) -> bool {
+    self.event_queue.is_empty()
+  }
+
   #[inline(always)]
   pub fn pop(&mut self) -> Option<Event<Message, Topic>> {
     let popped = self.event_queue.pop();
     if let Some(Event{ time, .. }) = &popped {
-      self.now = time.clone();
+      self.now = *time;
     }
 
     popped
   }
+
+  /// Removes the scheduled events for which `predicate` returns `true`, e.g. the infection
+  /// attempts of an epidemic that is already over, and returns how many it removed. This is
+  /// `O(n)`, so it is meant for occasional use. `now` is unchanged.
+  pub fn cancel<F>(&mut self, predicate: F) -> usize
+      where F: Fn(&Event<Message, Topic>) -> bool
+  {
+    let before = self.event_queue.len();
+    self.event_queue.retain(|event| !predicate(event));
+    before - self.event_queue.len()
+  }
+
+  /// Moves all of `other`'s events into this timeline. Afterward `now` is the later of the
+  /// two timelines' clocks. The events are renumbered as though they had been pushed onto this
+  /// timeline, after its own, in the order they were pushed onto `other`, so that their
+  /// sequence numbers and tie breaks don't collide with this timeline's.
+  pub fn merge(&mut self, other: Timeline<Message, Topic>) {
+    self.now = self.now.max(other.now);
+
+    let mut events = other.event_queue.into_vec();
+    events.sort_by_key(|event| event.sequence_number);
+    for event in events {
+      self.push(event);
+    }
+  }
+
+  /// Shifts every scheduled event `delta` later, e.g. to make up for a real-world delay after
+  /// the simulation was paused or restored. Events keep their order and tie breaks; `now` is
+  /// unchanged.
+  ///
+  /// Panics if a negative `delta` would move the next event before `now`.
+  pub fn reschedule_all_by(&mut self, delta: Time) {
+    if let Some(next_time) = self.next_time() {
+      assert!(next_time + delta >= self.now, "cannot reschedule events before the current time");
+    }
+
+    let mut events = std::mem::take(&mut self.event_queue).into_vec();
+    for event in &mut events {
+      event.time += delta;
+    }
+    self.event_queue = BinaryHeap::from(events);
+  }
+
+  /// All scheduled events, in no particular order.
+  pub fn events(&self) -> impl Iterator<Item = &Event<Message, Topic>> {
+    self.event_queue.iter()
+  }
+
+  /// All scheduled events in the order they will fire, with their names. This is
+  /// `O(n log n)`, so it is intended for debugging rather than for use in the event loop.
+  pub fn as_sorted_vec(&self) -> Vec<&Event<Message, Topic>> {
+    let mut events: Vec<&Event<Message, Topic>> = self.event_queue.iter().collect();
+    // `Event`'s ordering is reversed for the max heap, so we sort descending.
+    events.sort_by(|a, b| b.cmp(a));
+    events
+  }
+}
+
+// region Serialization
+
+/// What a `Timeline` is serialized as, so that a simulation can be checkpointed and restored.
+/// The heap is written out as a `Vec` of its events in the order they will fire.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedTimeline<E> {
+  now                 : Time,
+  tie_break           : TieBreak,
+  next_sequence_number: u64,
+  events              : Vec<E>,
+}
+
+#[cfg(feature = "serde")]
+impl<Message, Topic> serde::Serialize for Timeline<Message, Topic>
+    where Message: Clone + Debug + serde::Serialize,
+          Topic  : BoundedTopic + serde::Serialize
+{
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let serialized = SerializedTimeline {
+      now                 : self.now,
+      tie_break           : self.tie_break,
+      next_sequence_number: self.next_sequence_number,
+      events              : self.as_sorted_vec(),
+    };
+    serde::Serialize::serialize(&serialized, serializer)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, Message, Topic> serde::Deserialize<'de> for Timeline<Message, Topic>
+    where Message: Clone + Debug + serde::Deserialize<'de>,
+          Topic  : BoundedTopic + serde::Deserialize<'de>
+{
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let SerializedTimeline { now, tie_break, next_sequence_number, events }: SerializedTimeline<Event<Message, Topic>>
+      = serde::Deserialize::deserialize(deserializer)?;
+
+    let mut timeline = Timeline::new_with_tie_break(tie_break);
+    // `TieBreak::Random` draws once per push, so replaying the draws restores the generator.
+    if let TieBreak::Random(_) = tie_break {
+      for _ in 0..next_sequence_number {
+        timeline.rng.random::<u64>();
+      }
+    }
+    timeline.now                  = now;
+    timeline.next_sequence_number = next_sequence_number;
+    timeline.event_queue          = BinaryHeap::from(events);
+
+    Ok(timeline)
+  }
 }
 
+// endregion Serialization
+
 /*
 impl<Message, Topic> Actor<Message, Topic> for Timeline<Message, Topic>
     where Message: Clone + Debug,
@@ -148,3 +383,171 @@ impl<Message, Topic> Actor<Message, Topic> for Timeline<Message, Topic>
   }
 }
 */
+
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    actor::ActorHandle,
+    message::{Channel, Envelope}
+  };
+  use super::*;
+
+  fn event(time: f64) -> Event<(), ()> {
+    actor_event(time, 0)
+  }
+
+  fn actor_event(time: f64, from: ActorHandle) -> Event<(), ()> {
+    let envelope = Envelope::new(from, Channel::ScheduleEvent, None, Some(time.into()));
+    Event::new(time.into(), RcEnvelope::new(envelope))
+  }
+
+  /// The order in which the events fire, identified by their sequence numbers.
+  fn firing_order(timeline: &Timeline<(), ()>) -> Vec<u64> {
+    timeline.as_sorted_vec().iter().map(|event| event.sequence_number).collect()
+  }
+
+  #[test]
+  fn tie_break_strategies() {
+    let schedule = |strategy| {
+      let mut timeline = Timeline::new_with_tie_break(strategy);
+      timeline.push(actor_event(1.0, 2));
+      timeline.push(actor_event(1.0, 1));
+      timeline.push(actor_event(0.0, 3));
+      timeline.push(actor_event(1.0, 2));
+      timeline
+    };
+
+    assert_eq!(firing_order(&schedule(TieBreak::OldestFirst)), vec![2, 0, 1, 3]);
+    assert_eq!(firing_order(&schedule(TieBreak::NewestFirst)), vec![2, 3, 1, 0]);
+    assert_eq!(firing_order(&schedule(TieBreak::ActorAscending)), vec![2, 1, 0, 3]);
+    // A random order is reproducible from its seed.
+    assert_eq!(firing_order(&schedule(TieBreak::Random(7))), firing_order(&schedule(TieBreak::Random(7))));
+    assert_eq!(firing_order(&schedule(TieBreak::Random(7)))[0], 2);
+  }
+
+  #[test]
+  fn peek_leaves_the_next_event_in_place() {
+    let mut timeline = Timeline::default();
+    assert!(timeline.peek().is_none());
+    assert!(timeline.is_empty());
+
+    timeline.push(event(2.0));
+    timeline.push(event(1.0));
+    assert_eq!(timeline.peek().map(|event| event.sequence_number), Some(1));
+    assert_eq!(timeline.len(), 2);
+    assert_eq!(timeline.now(), 0.0);
+
+    assert_eq!(timeline.pop().map(|event| event.sequence_number), Some(1));
+    assert_eq!(timeline.peek().map(|event| event.time.0), Some(2.0));
+    assert_eq!(timeline.len(), 1);
+  }
+
+  #[test]
+  fn cancel_removes_matching_events() {
+    let mut timeline = Timeline::default();
+    for time in [3.0, 1.0, 4.0, 2.0] {
+      timeline.push(event(time));
+    }
+    timeline.pop();
+
+    assert_eq!(timeline.cancel(|event| event.time.0 > 2.5), 2);
+    assert_eq!(timeline.cancel(|event| event.time.0 > 2.5), 0);
+    assert_eq!(timeline.now(), 1.0);
+    assert_eq!(firing_order(&timeline), vec![3]);
+  }
+
+  #[test]
+  fn merge_preserves_time_order() {
+    let mut first  = Timeline::default();
+    let mut second = Timeline::default();
+    first.push(event(1.0));
+    first.push(event(4.0));
+    second.push(event(0.5));
+    second.push(event(3.0));
+    second.push(event(2.0));
+    second.pop();
+
+    first.merge(second);
+
+    assert_eq!(first.now(), 0.5);
+    let times: Vec<f64> = first.as_sorted_vec().iter().map(|event| event.time.0).collect();
+    assert_eq!(times, vec![1.0, 2.0, 3.0, 4.0]);
+  }
+
+  #[test]
+  fn merged_events_are_renumbered() {
+    let mut first  = Timeline::default();
+    let mut second = Timeline::default();
+    first.push(event(1.0));
+    first.push(event(2.0));
+    second.push(event(2.0));
+    second.push(event(1.0));
+
+    first.merge(second);
+    first.push(event(1.0));
+
+    // Ties go to the oldest, and the merged events count as pushed after `first`'s own.
+    assert_eq!(firing_order(&first), vec![0, 3, 4, 1, 2]);
+  }
+
+  #[test]
+  fn reschedule_all_by_shifts_every_event() {
+    let mut timeline = Timeline::default();
+    timeline.push(event(3.0));
+    timeline.push(event(1.0));
+    timeline.push(event(1.0));
+    timeline.pop();
+
+    timeline.reschedule_all_by(10.0.into());
+
+    assert_eq!(timeline.now(), 1.0);
+    let times: Vec<f64> = timeline.as_sorted_vec().iter().map(|event| event.time.0).collect();
+    assert_eq!(times, vec![11.0, 13.0]);
+    assert_eq!(firing_order(&timeline), vec![2, 0]);
+  }
+
+  #[test]
+  #[should_panic(expected = "before the current time")]
+  fn reschedule_all_by_rejects_the_past() {
+    let mut timeline = Timeline::default();
+    timeline.push(event(1.0));
+    timeline.push(event(2.0));
+    timeline.pop();
+
+    timeline.reschedule_all_by((-2.0).into());
+  }
+
+  #[test]
+  fn events_carry_their_envelopes_names() {
+    let mut timeline = Timeline::<(), ()>::default();
+    let named = Envelope::new(0, Channel::ScheduleEvent, None, Some(2.0.into())).with_name("recover");
+    timeline.push(Event::new(2.0.into(), RcEnvelope::new(named)));
+    timeline.push(event(1.0));
+
+    let names: Vec<_> = timeline.as_sorted_vec().iter().map(|event| event.name).collect();
+    assert_eq!(names, vec![None, Some("recover")]);
+    assert!(format!("{:?}", timeline.as_sorted_vec()[1]).contains("\"recover\""));
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn restored_timeline_continues_where_it_left_off() {
+    let mut timeline = Timeline::new_with_tie_break(TieBreak::Random(3));
+    timeline.push(event(1.0));
+    timeline.push(event(2.0));
+    timeline.push(event(2.0));
+    timeline.pop();
+
+    let json         = serde_json::to_string(&timeline).unwrap();
+    let mut restored = serde_json::from_str::<Timeline<(), ()>>(&json).unwrap();
+    assert_eq!(restored.now(), 1.0);
+    assert_eq!(firing_order(&restored), firing_order(&timeline));
+
+    // Events pushed after the restore get the same sequence numbers and tie breaks.
+    timeline.push(event(2.0));
+    restored.push(event(2.0));
+    assert_eq!(firing_order(&restored), firing_order(&timeline));
+    assert_eq!(firing_order(&restored).len(), 3);
+  }
+}